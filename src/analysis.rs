@@ -0,0 +1,259 @@
+use slotmap::SecondaryMap;
+
+use crate::node::NodeKind;
+use crate::sim::Simulation;
+use crate::{EdgeId, NodeId};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Warning {
+    /// Part of a feedback loop, which is fine as long as it doesn't grow.
+    Cycle,
+    /// A loop with more ways around than nodes, so the signal count
+    /// multiplies with every lap.
+    Growth,
+    /// A forward edge pointing back in time, signals on it never arrive.
+    Stalled,
+    /// No spawner or trigger can reach this node.
+    Unreachable,
+}
+impl Warning {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Warning::Cycle => "loop",
+            Warning::Growth => "growing loop",
+            Warning::Stalled => "stalled, make it a wrap",
+            Warning::Unreachable => "unreachable",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Analysis {
+    pub nodes: SecondaryMap<NodeId, Vec<Warning>>,
+    pub edges: SecondaryMap<EdgeId, Vec<Warning>>,
+}
+impl Analysis {
+    fn warn_node(&mut self, id: NodeId, warning: Warning) {
+        let warnings = self.nodes.entry(id).unwrap().or_default();
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    fn warn_edge(&mut self, id: EdgeId, warning: Warning) {
+        let warnings = self.edges.entry(id).unwrap().or_default();
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    pub fn count_nodes(&self, warning: Warning) -> usize {
+        self.nodes.values().filter(|w| w.contains(&warning)).count()
+    }
+    pub fn count_edges(&self, warning: Warning) -> usize {
+        self.edges.values().filter(|w| w.contains(&warning)).count()
+    }
+}
+
+/// Where signals can go from each node, through enabled edges that arrive.
+/// Macros are seen through: an edge into a macro continues at its inlet,
+/// an edge out of it starts at the matching outlet. Ports that don't
+/// exist are skipped, so every node in the lists has an entry.
+pub type Flow = SecondaryMap<NodeId, Vec<(NodeId, EdgeId)>>;
+
+pub fn flow_graph(sim: &Simulation) -> Flow {
+    let port = |id: NodeId, port: usize, inlet: bool| match &sim.nodes[id].kind {
+        NodeKind::Macro { inlets, outlets } => {
+            let ports = if inlet { inlets } else { outlets };
+            ports.get(port).copied()
+        }
+        _ => Some(id),
+    };
+    let mut flow = Flow::new();
+    for id in sim.nodes.keys() {
+        flow.insert(id, vec![]);
+    }
+    for (id, edge) in &sim.edges {
+        if !edge.enabled || edge.duration(&sim.nodes).is_none() {
+            continue;
+        }
+        let (u, v) = edge.nodes;
+        if let (Some(u), Some(v)) = (port(u, edge.ports.0, false), port(v, edge.ports.1, true)) {
+            if flow.contains_key(v) {
+                if let Some(out) = flow.get_mut(u) {
+                    out.push((v, id));
+                }
+            }
+        }
+    }
+    flow
+}
+
+/// Finds loops, runaway loops, stalled edges and unreachable nodes.
+pub fn analyze(sim: &Simulation) -> Analysis {
+    let mut analysis = Analysis::default();
+    for (id, edge) in &sim.edges {
+        if edge.duration(&sim.nodes).is_none() {
+            analysis.warn_edge(id, Warning::Stalled);
+        }
+    }
+    let flow = flow_graph(sim);
+
+    let mut reached: SecondaryMap<NodeId, ()> = SecondaryMap::new();
+    let mut stack: Vec<NodeId> = sim
+        .nodes
+        .iter()
+        .filter(|(_, node)| {
+            matches!(
+                node.kind,
+                NodeKind::Spawner { .. }
+                    | NodeKind::Euclid(_)
+                    | NodeKind::Pattern(_)
+                    | NodeKind::Trigger(_)
+            )
+        })
+        .map(|(id, _)| id)
+        .collect();
+    while let Some(id) = stack.pop() {
+        if reached.insert(id, ()).is_none() {
+            stack.extend(flow.get(id).into_iter().flatten().map(|&(v, _)| v));
+        }
+    }
+    for (id, node) in &sim.nodes {
+        if !reached.contains_key(id) && !matches!(node.kind, NodeKind::Macro { .. }) {
+            analysis.warn_node(id, Warning::Unreachable);
+        }
+    }
+
+    for component in strongly_connected(&flow) {
+        let inside: Vec<EdgeId> = component
+            .iter()
+            .flat_map(|&u| &flow[u])
+            .filter(|(v, _)| component.contains(v))
+            .map(|&(_, e)| e)
+            .collect();
+        if inside.is_empty() {
+            continue;
+        }
+        let warning = if inside.len() > component.len() {
+            Warning::Growth
+        } else {
+            Warning::Cycle
+        };
+        for &id in &component {
+            analysis.warn_node(id, warning);
+        }
+        for id in inside {
+            analysis.warn_edge(id, warning);
+        }
+    }
+    analysis
+}
+
+/// Tarjan's algorithm.
+fn strongly_connected(flow: &Flow) -> Vec<Vec<NodeId>> {
+    struct Tarjan<'a> {
+        flow: &'a Flow,
+        index: SecondaryMap<NodeId, (usize, usize)>,
+        stack: Vec<NodeId>,
+        on_stack: SecondaryMap<NodeId, ()>,
+        components: Vec<Vec<NodeId>>,
+    }
+    impl Tarjan<'_> {
+        fn visit(&mut self, u: NodeId) {
+            let index = self.index.len();
+            self.index.insert(u, (index, index));
+            self.stack.push(u);
+            self.on_stack.insert(u, ());
+            for &(v, _) in &self.flow[u] {
+                if !self.index.contains_key(v) {
+                    self.visit(v);
+                    self.index[u].1 = self.index[u].1.min(self.index[v].1);
+                } else if self.on_stack.contains_key(v) {
+                    self.index[u].1 = self.index[u].1.min(self.index[v].0);
+                }
+            }
+            if self.index[u].0 == self.index[u].1 {
+                let mut component = vec![];
+                while let Some(v) = self.stack.pop() {
+                    self.on_stack.remove(v);
+                    component.push(v);
+                    if v == u {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+    let mut tarjan = Tarjan {
+        flow,
+        index: SecondaryMap::new(),
+        stack: vec![],
+        on_stack: SecondaryMap::new(),
+        components: vec![],
+    };
+    for u in flow.keys() {
+        if !tarjan.index.contains_key(u) {
+            tarjan.visit(u);
+        }
+    }
+    tarjan.components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::Edge;
+    use crate::node::Node;
+    use crate::PX_PER_BAR;
+
+    fn chain(sim: &mut Simulation, xs: &[f32]) -> Vec<NodeId> {
+        let ids: Vec<NodeId> = xs
+            .iter()
+            .map(|&x| sim.add_node(Node::new(x * PX_PER_BAR, 0.0)))
+            .collect();
+        for pair in ids.windows(2) {
+            sim.add_edge(Edge::new(pair[0], pair[1]));
+        }
+        ids
+    }
+
+    #[test]
+    fn loops_and_growth() {
+        let mut sim = Simulation::new();
+        let ids = chain(&mut sim, &[0.0, 1.0, 2.0]);
+        sim.nodes[ids[0]].kind = NodeKind::spawner(1.0);
+        let back = sim.add_edge(Edge::new_auto(ids[2], ids[1], &sim.nodes));
+        let analysis = analyze(&sim);
+        assert_eq!(analysis.count_nodes(Warning::Cycle), 2);
+        assert!(analysis.edges[back] == [Warning::Cycle]);
+        // a second way around makes every lap double the signals
+        sim.add_edge(Edge::new_auto(ids[2], ids[1], &sim.nodes));
+        let analysis = analyze(&sim);
+        assert_eq!(analysis.count_nodes(Warning::Growth), 2);
+        assert!(analysis.nodes.get(ids[0]).is_none());
+    }
+
+    #[test]
+    fn stalled_and_unreachable() {
+        let mut sim = Simulation::new();
+        let ids = chain(&mut sim, &[1.0, 0.0]);
+        let analysis = analyze(&sim);
+        assert_eq!(analysis.count_edges(Warning::Stalled), 1);
+        assert_eq!(analysis.count_nodes(Warning::Unreachable), 2);
+        sim.nodes[ids[0]].kind = NodeKind::spawner(1.0);
+        assert_eq!(analyze(&sim).count_nodes(Warning::Unreachable), 1);
+    }
+
+    #[test]
+    fn missing_ports_are_skipped() {
+        let mut sim = Simulation::new();
+        let ids = chain(&mut sim, &[0.0, 1.0]);
+        let inlet = sim.add_node(Node::new(0.0, 0.0));
+        sim.nodes[ids[1]].kind = NodeKind::Macro {
+            inlets: vec![inlet],
+            outlets: vec![],
+        };
+        sim.nodes.remove(inlet);
+        analyze(&sim);
+    }
+}
@@ -1,4 +1,7 @@
-use macroquad::audio::{self, Sound};
+use std::collections::HashMap;
+
+use macroquad::audio::{self, PlaySoundParams, Sound};
+use macroquad::file::load_file;
 
 const AUDIO_FILES: &[&str] = &[
     "Slam_Layer_Clap06.wav",
@@ -12,21 +15,71 @@ const AUDIO_FILES: &[&str] = &[
     "Slam_Layer_Snare01.wav",
     "Slam_R5_Crash02.wav",
 ];
+/// Four octaves either way, which also bounds the cache of pitched copies.
+const MAX_TRANSPOSE: i32 = 48;
+pub struct Sample {
+    sound: Sound,
+    data: Vec<u8>,
+}
 pub struct Audio {
-    samples: Vec<Sound>,
+    samples: Vec<Sample>,
+    pitched: HashMap<(usize, i32), Sound>,
 }
-pub async fn load_samples() -> Vec<Sound> {
+pub async fn load_samples() -> Vec<Sample> {
     let mut samples = vec![];
     for s in AUDIO_FILES {
-        samples.push(audio::load_sound(s).await.unwrap());
+        let data = load_file(s).await.unwrap();
+        let sound = audio::load_sound_from_bytes(&data).await.unwrap();
+        samples.push(Sample { sound, data });
     }
     samples
 }
+/// Shifts the pitch by rewriting the sample rate in the wav header,
+/// the mixer resamples it back which changes speed and pitch together.
+fn repitch_wav(data: &[u8], semitones: i32) -> Vec<u8> {
+    let mut data = data.to_vec();
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        if &data[pos..pos + 4] == b"fmt " && pos + 24 <= data.len() {
+            let fmt = pos + 8;
+            let rate = u32::from_le_bytes(data[fmt + 4..fmt + 8].try_into().unwrap());
+            let block_align = u16::from_le_bytes(data[fmt + 12..fmt + 14].try_into().unwrap());
+            let rate = (rate as f32 * 2f32.powf(semitones as f32 / 12.0)).round() as u32;
+            data[fmt + 4..fmt + 8].copy_from_slice(&rate.to_le_bytes());
+            data[fmt + 8..fmt + 12]
+                .copy_from_slice(&rate.saturating_mul(block_align as u32).to_le_bytes());
+            break;
+        }
+        pos += 8 + size + size % 2;
+    }
+    data
+}
 impl Audio {
-    pub fn new(samples: Vec<Sound>) -> Self {
-        Self { samples }
+    pub fn new(samples: Vec<Sample>) -> Self {
+        Self {
+            samples,
+            pitched: HashMap::new(),
+        }
     }
-    pub fn play(&self, idx: usize) {
-        audio::play_sound_once(&self.samples[idx]);
+    pub async fn play(&mut self, idx: usize, velocity: f32, transpose: i32) {
+        let transpose = transpose.clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE);
+        let sound = if transpose == 0 {
+            self.samples[idx].sound.clone()
+        } else if let Some(sound) = self.pitched.get(&(idx, transpose)) {
+            sound.clone()
+        } else {
+            let data = repitch_wav(&self.samples[idx].data, transpose);
+            let sound = audio::load_sound_from_bytes(&data).await.unwrap();
+            self.pitched.insert((idx, transpose), sound.clone());
+            sound
+        };
+        audio::play_sound(
+            &sound,
+            PlaySoundParams {
+                looped: false,
+                volume: velocity.max(0.0),
+            },
+        );
     }
 }
@@ -1,17 +1,106 @@
-use macroquad::math::Vec2;
+use glam::Vec2;
 use slotmap::SlotMap;
 
-use crate::{Node, NodeId};
+use crate::{Node, NodeId, NodeKind, BAR_TIME, PX_PER_BAR, SIGNAL_SPEED};
 
+const CURVE_SEGMENTS: usize = 24;
+
+#[derive(Clone, PartialEq)]
 pub struct Edge {
     pub nodes: (NodeId, NodeId),
+    /// Outlet of the source and inlet of the target, used when they are macros.
+    pub ports: (usize, usize),
+    pub kind: EdgeKind,
+    pub enabled: bool,
+    pub delay: f32,
+    pub probability: f32,
+    pub label: String,
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    Forward,
+    /// Takes a fixed number of bars regardless of geometry.
+    Loop {
+        bars: f32,
+    },
+    /// Wraps around a cycle of `bars`, arriving where the target sits in the next cycle.
+    Wrap {
+        bars: f32,
+    },
 }
 impl Edge {
     pub fn new(u: NodeId, v: NodeId) -> Self {
         assert!(u != v);
-        Self { nodes: (u, v) }
+        Self {
+            nodes: (u, v),
+            ports: (0, 0),
+            kind: EdgeKind::Forward,
+            enabled: true,
+            delay: 0.0,
+            probability: 1.0,
+            label: String::new(),
+        }
+    }
+    pub fn new_auto(u: NodeId, v: NodeId, nodes: &SlotMap<NodeId, Node>) -> Self {
+        let mut edge = Self::new(u, v);
+        let dx = edge.dx(nodes);
+        if dx < 0.0 {
+            edge.kind = EdgeKind::Wrap {
+                bars: (-dx / PX_PER_BAR).ceil(),
+            };
+        }
+        edge
+    }
+    pub fn is_loop(&self) -> bool {
+        !matches!(self.kind, EdgeKind::Forward)
+    }
+    /// Horizontal position used for timing, macros are timed from their port inside.
+    pub fn anchor_x(nodes: &SlotMap<NodeId, Node>, id: NodeId, port: usize, source: bool) -> f32 {
+        let node = &nodes[id];
+        let port = match &node.kind {
+            NodeKind::Macro { outlets, .. } if source => outlets.get(port),
+            NodeKind::Macro { inlets, .. } => inlets.get(port),
+            _ => None,
+        };
+        node.pos.x + port.and_then(|&p| nodes.get(p)).map_or(0.0, |p| p.pos.x)
+    }
+    pub fn dx(&self, nodes: &SlotMap<NodeId, Node>) -> f32 {
+        Self::anchor_x(nodes, self.nodes.1, self.ports.1, false)
+            - Self::anchor_x(nodes, self.nodes.0, self.ports.0, true)
+    }
+    /// Travel time of a signal along the edge, `None` if it would never arrive.
+    pub fn duration(&self, nodes: &SlotMap<NodeId, Node>) -> Option<f32> {
+        let dx = self.dx(nodes);
+        match self.kind {
+            EdgeKind::Forward if dx < 0.0 => None,
+            EdgeKind::Forward => Some(dx / SIGNAL_SPEED),
+            EdgeKind::Loop { bars } => Some(bars.max(0.0) * BAR_TIME),
+            EdgeKind::Wrap { bars } => {
+                let cycle = bars.max(1.0 / 16.0) * PX_PER_BAR;
+                let px = dx.rem_euclid(cycle);
+                Some(if px <= 0.0 { cycle } else { px } / SIGNAL_SPEED)
+            }
+        }
+    }
+    pub fn point_at(&self, nodes: &SlotMap<NodeId, Node>, t: f32) -> Vec2 {
+        let a = nodes[self.nodes.0].pos;
+        let b = nodes[self.nodes.1].pos;
+        if !self.is_loop() {
+            return a.lerp(b, t);
+        }
+        let ctrl =
+            (a + b) / 2.0 + (b - a).perp().normalize_or_zero() * (0.35 * a.distance(b) + 30.0);
+        a.lerp(ctrl, t).lerp(ctrl.lerp(b, t), t)
     }
     pub fn distance_squared(&self, pos: Vec2, nodes: &SlotMap<NodeId, Node>, clamp: f32) -> f32 {
+        if self.is_loop() {
+            return (0..=CURVE_SEGMENTS)
+                .map(|i| {
+                    let t = clamp / 2.0 + (1.0 - clamp) * i as f32 / CURVE_SEGMENTS as f32;
+                    self.point_at(nodes, t).distance_squared(pos)
+                })
+                .fold(f32::INFINITY, f32::min);
+        }
         let a = nodes[self.nodes.0].pos;
         let b = nodes[self.nodes.1].pos;
         let l_sq = a.distance_squared(b);
@@ -22,4 +111,9 @@ impl Edge {
         let proj = a + t * (b - a);
         proj.distance_squared(pos)
     }
+    pub fn curve_points(&self, nodes: &SlotMap<NodeId, Node>) -> Vec<Vec2> {
+        (0..=CURVE_SEGMENTS)
+            .map(|i| self.point_at(nodes, i as f32 / CURVE_SEGMENTS as f32))
+            .collect()
+    }
 }
@@ -0,0 +1,636 @@
+use macroquad::hash;
+use macroquad::prelude::*;
+use macroquad::ui::{root_ui, widgets, Ui};
+use slotmap::Key as _;
+
+use crate::*;
+
+const MERGE_MODES: &[&str] = &["Off", "Simultaneous", "Window"];
+const STEPS_PER_ROW: usize = 8;
+const EDGE_KINDS: &[&str] = &["Forward", "Loop", "Wrap"];
+const QUANTA: &[f32] = &[0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const QUANTUM_NAMES: &[&str] = &["1/4", "1/2", "1", "2", "4", "8"];
+
+pub fn draw_inspector(state: &mut State) {
+    let Some(sel) = state.selection else {
+        return;
+    };
+    let (mut open, mut duplicate) = (false, false);
+    widgets::Window::new(
+        hash!(),
+        vec2(screen_width() - 250.0, 10.0),
+        vec2(240.0, 300.0),
+    )
+    .label("Inspector")
+    .ui(&mut root_ui(), |ui| match sel {
+        Selection::Node(id) => {
+            if matches!(state.sim.nodes[id].kind, NodeKind::Macro { .. }) {
+                open = ui.button(None, "Open");
+                ui.same_line(0.0);
+                duplicate = ui.button(None, "Duplicate");
+            }
+            let mut muted = state.sim.scenes[state.sim.scene].muted.contains_key(id);
+            ui.checkbox(hash!(), "muted in scene", &mut muted);
+            if muted != state.sim.scenes[state.sim.scene].muted.contains_key(id) {
+                state.sim.perform(Action::ToggleMute(id));
+            }
+            ui.checkbox(hash!(), "breakpoint", &mut state.sim.nodes[id].breakpoint);
+            let before = state.sim.nodes[id].clone();
+            node_inspector(
+                ui,
+                &mut state.sim.nodes[id],
+                state.sim.time,
+                state.sim.launch_quantum,
+            );
+            state.sim.edited |= state.sim.nodes[id] != before;
+        }
+        Selection::Edge(id) => {
+            let (u, v) = state.sim.edges[id].nodes;
+            let is_macro = |id| matches!(state.sim.nodes[id].kind, NodeKind::Macro { .. });
+            let macros = (is_macro(u), is_macro(v));
+            let before = state.sim.edges[id].clone();
+            let edge = &mut state.sim.edges[id];
+            edge_inspector(ui, edge, macros);
+            if (before.kind, before.ports) != (edge.kind, edge.ports) {
+                state.sim.reschedule_edges(&[id]);
+            }
+            state.sim.edited |= state.sim.edges[id] != before;
+        }
+    });
+    if let Selection::Node(id) = sel {
+        if open {
+            state.set_view(Some(id));
+        } else if duplicate {
+            let copy = state.sim.duplicate(id, vec2(0.0, 3.0 * NODE_RADIUS));
+            state.selection = Some(Selection::Node(copy));
+        }
+    }
+}
+
+pub fn draw_view_panel(state: &mut State) {
+    let Some(view) = state.view else {
+        return;
+    };
+    let mut up = false;
+    widgets::Window::new(
+        hash!(),
+        vec2(10.0, screen_height() - 260.0),
+        vec2(200.0, 60.0),
+    )
+    .label("Macro")
+    .ui(&mut root_ui(), |ui| {
+        ui.label(
+            None,
+            &format!("editing macro, {} nodes", state.sim.descendants(view).len()),
+        );
+        up = ui.button(None, "Up");
+    });
+    if up {
+        let parent = state.sim.nodes[view].parent;
+        state.set_view(parent);
+    }
+}
+
+pub fn draw_step_editor(state: &mut State) {
+    let Some(Selection::Node(id)) = state.selection else {
+        return;
+    };
+    let pos = state.sim.nodes[id].pos - state.camera_pos;
+    let NodeKind::Pattern(pattern) = &mut state.sim.nodes[id].kind else {
+        return;
+    };
+    let rows = pattern.steps.len().div_ceil(STEPS_PER_ROW);
+    let mut changed = false;
+    widgets::Window::new(
+        hash!("steps", id.data().as_ffi()),
+        pos + vec2(NODE_RADIUS * 2.0, NODE_RADIUS),
+        vec2(
+            STEPS_PER_ROW as f32 * 24.0 + 10.0,
+            rows as f32 * 24.0 + 30.0,
+        ),
+    )
+    .label("Steps")
+    .ui(&mut root_ui(), |ui| {
+        for (i, step) in pattern.steps.iter_mut().enumerate() {
+            if i % STEPS_PER_ROW != 0 {
+                ui.same_line(0.0);
+            }
+            let text = match step {
+                Step::Off => ".",
+                Step::On => "o",
+                Step::Accent => "O",
+            };
+            if widgets::Button::new(text).size(vec2(20.0, 20.0)).ui(ui) {
+                *step = step.next();
+                changed = true;
+            }
+        }
+    });
+    state.sim.edited |= changed;
+}
+
+pub fn draw_transport(state: &mut State) {
+    widgets::Window::new(
+        hash!(),
+        vec2(screen_width() / 2.0 - 130.0, 10.0),
+        vec2(260.0, 100.0),
+    )
+    .label("Transport")
+    .ui(&mut root_ui(), |ui| {
+        let play = if state.sim.paused { "Play" } else { "Pause" };
+        if ui.button(None, play) {
+            state.sim.perform(Action::Pause);
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Stop") {
+            state.stop();
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Rewind") {
+            state.rewind();
+        }
+        ui.same_line(0.0);
+        let (bar, beat) = state.sim.bar_beat();
+        ui.label(None, &format!("{bar}:{beat}"));
+        let mut quantum = QUANTA
+            .iter()
+            .position(|&q| q == state.sim.launch_quantum)
+            .unwrap_or(2);
+        ui.combo_box(hash!(), "launch (bars)", QUANTUM_NAMES, &mut quantum);
+        state.sim.launch_quantum = QUANTA[quantum];
+        ui.label(None, &format!("seed {:016x}", state.sim.seed));
+        ui.same_line(0.0);
+        ui.checkbox(hash!(), "lock", &mut state.sim.seed_locked);
+        ui.same_line(0.0);
+        if ui.button(None, "Re-roll") {
+            state.sim.reroll();
+            state.rewind();
+        }
+    });
+}
+
+pub fn draw_scene_panel(state: &mut State) {
+    widgets::Window::new(
+        hash!(),
+        vec2(screen_width() - 250.0, screen_height() - 110.0),
+        vec2(240.0, 100.0),
+    )
+    .label("Scenes")
+    .ui(&mut root_ui(), |ui| {
+        let queued = state.sim.queued_scene.map(|(scene, _)| scene);
+        for i in 0..state.sim.scenes.len() {
+            let name = &state.sim.scenes[i].name;
+            let text = if i == state.sim.scene {
+                format!("[{name}]")
+            } else if Some(i) == queued {
+                format!("{name}...")
+            } else {
+                name.clone()
+            };
+            if i > 0 {
+                ui.same_line(0.0);
+            }
+            if ui.button(None, text) {
+                state.sim.perform(Action::QueueScene(i));
+            }
+        }
+        if ui.button(None, "New") {
+            let scene = state.sim.add_scene();
+            state.sim.perform(Action::QueueScene(scene));
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Now") {
+            state.sim.perform(Action::SceneNow);
+        }
+    });
+}
+
+pub fn draw_arrangement(state: &mut State) {
+    let mut play = false;
+    let before = (state.sim.arrangement.clone(), state.sim.follow_arrangement);
+    widgets::Window::new(
+        hash!(),
+        vec2(screen_width() - 500.0, screen_height() - 250.0),
+        vec2(240.0, 240.0),
+    )
+    .label("Arrangement")
+    .ui(&mut root_ui(), |ui| {
+        let names: Vec<&str> = state.sim.scenes.iter().map(|s| s.name.as_str()).collect();
+        let mut remove = None;
+        for (i, section) in state.sim.arrangement.iter_mut().enumerate() {
+            ui.combo_box(hash!("scene", i), "scene", &names, &mut section.scene);
+            ui.drag(hash!("bars", i), "bars", (1, 256), &mut section.bars);
+            if ui.button(None, format!("Remove {}", i + 1)) {
+                remove = Some(i);
+            }
+            ui.separator();
+        }
+        if let Some(i) = remove {
+            state.sim.arrangement.remove(i);
+        }
+        if ui.button(None, "Add") {
+            state.sim.arrangement.push(Section {
+                scene: state.sim.scene,
+                bars: 4,
+            });
+        }
+        ui.same_line(0.0);
+        play = ui.button(None, "Play song");
+        ui.checkbox(hash!(), "follow", &mut state.sim.follow_arrangement);
+        let (bar, _) = state.sim.bar_beat();
+        ui.label(
+            None,
+            &format!("bar {bar} of {}", state.sim.arrangement_bars()),
+        );
+    });
+    if before != (state.sim.arrangement.clone(), state.sim.follow_arrangement) {
+        state.sim.edited = true;
+    }
+    if play {
+        state.sim.play_song();
+    }
+}
+
+pub fn draw_recorder(state: &mut State) {
+    widgets::Window::new(
+        hash!(),
+        vec2(220.0, screen_height() - 110.0),
+        vec2(220.0, 100.0),
+    )
+    .label("Recorder")
+    .ui(&mut root_ui(), |ui| {
+        let rec = if state.sim.recorder.recording {
+            "Stop rec"
+        } else {
+            "Rec"
+        };
+        if ui.button(None, rec) {
+            if state.sim.recorder.recording {
+                state.sim.recorder.recording = false;
+            } else {
+                state.sim.record();
+            }
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Replay") {
+            state.sim.replay();
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Save") {
+            state.sim.recorder.status = match state.sim.save_take() {
+                Ok(()) => "saved".to_string(),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Load") {
+            state.sim.recorder.status = match state.sim.load_take() {
+                Ok(()) => "loaded".to_string(),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+        let events = state.sim.recorder.take.len();
+        let status = match state.sim.recorder.replay {
+            Some(next) => format!("replaying {next}/{events}"),
+            None if state.sim.recorder.recording => format!("recording, {events} events"),
+            None => format!("{events} events"),
+        };
+        ui.label(None, &status);
+        ui.label(None, &state.sim.recorder.status);
+    });
+}
+
+pub fn draw_preview_panel(state: &mut State) {
+    widgets::Window::new(
+        hash!(),
+        vec2(screen_width() - 500.0, 10.0),
+        vec2(240.0, 200.0),
+    )
+    .label("Preview")
+    .ui(&mut root_ui(), |ui| {
+        let preview = &mut state.preview;
+        ui.checkbox(hash!(), "show schedule from bar 1", &mut preview.show);
+        ui.drag(hash!(), "bars", (1, 16), &mut preview.bars);
+        if ui.button(None, "Pin") {
+            preview.pinned = Some(preview.schedule.clone());
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Unpin") {
+            preview.pinned = None;
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Save") {
+            preview.status = match preview.save() {
+                Ok(()) => "saved".to_string(),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+        let text = match &preview.pinned {
+            Some(pinned) => format!(
+                "{} triggers, {} differ from pin",
+                preview.schedule.len(),
+                difference(&preview.schedule, pinned)
+            ),
+            None => format!("{} triggers", preview.schedule.len()),
+        };
+        ui.label(None, &text);
+        ui.separator();
+        let period = state.sim.period();
+        let text = match period.bars() {
+            Some(bars) if period.approximate => format!("repeats every ~{bars} bars"),
+            Some(bars) => format!("repeats every {bars} bars"),
+            None => "no exact period".to_string(),
+        };
+        ui.label(None, &text);
+        let preview = &mut state.preview;
+        ui.drag(hash!(), "loop start", (0.0, 256.0), &mut preview.loop_start);
+        ui.drag(hash!(), "loop bars", (0.25, 256.0), &mut preview.loop_bars);
+        if ui.button(None, "Use period") {
+            if let Some(bars) = period.bars() {
+                preview.loop_start = bars;
+                preview.loop_bars = bars;
+            }
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Export loop") {
+            preview.status = match state.sim.export_loop(preview.loop_start, preview.loop_bars) {
+                Ok(()) => "exported".to_string(),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+        ui.label(None, &preview.status);
+    });
+}
+
+pub fn draw_signal_panel(state: &mut State) {
+    let limits = state.sim.limits.clone();
+    widgets::Window::new(
+        hash!(),
+        vec2(10.0, screen_height() - 190.0),
+        vec2(200.0, 180.0),
+    )
+    .label("Signals")
+    .ui(&mut root_ui(), |ui| {
+        ui.label(None, &format!("in flight: {}", state.sim.signals.len()));
+        ui.drag(
+            hash!(),
+            "max hops",
+            (1, 1024),
+            &mut state.sim.limits.max_hops,
+        );
+        ui.drag(
+            hash!(),
+            "ttl (bars)",
+            (1.0, 1024.0),
+            &mut state.sim.limits.ttl_bars,
+        );
+        ui.drag(
+            hash!(),
+            "cap",
+            (1, 100000),
+            &mut state.sim.limits.max_signals,
+        );
+        if ui.button(None, "Kill all (K)") {
+            state.sim.kill_signals();
+        }
+        let analysis = &state.analysis;
+        ui.label(
+            None,
+            &format!(
+                "in growing loops: {} nodes",
+                analysis.count_nodes(Warning::Growth)
+            ),
+        );
+        ui.label(
+            None,
+            &format!(
+                "stalled: {} edges, unreachable: {} nodes",
+                analysis.count_edges(Warning::Stalled),
+                analysis.count_nodes(Warning::Unreachable)
+            ),
+        );
+    });
+    state.sim.edited |= limits != state.sim.limits;
+}
+
+pub fn draw_debugger(state: &mut State) {
+    const ROWS: usize = 8;
+    widgets::Window::new(
+        hash!(),
+        vec2(10.0, screen_height() - 420.0),
+        vec2(200.0, 220.0),
+    )
+    .label("Debugger")
+    .ui(&mut root_ui(), |ui| {
+        if ui.button(None, "Step (.)") {
+            state.sim.single_step = true;
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Continue") && state.sim.paused {
+            state.sim.perform(Action::Pause);
+        }
+        let name = |id: NodeId| format!("n{}", id.data().as_ffi() as u32);
+        let halted = match state.sim.halted {
+            Some(id) => format!("halted at {}", name(id)),
+            None => "running".to_string(),
+        };
+        ui.label(None, &halted);
+        ui.separator();
+        let mut signals: Vec<&Signal> = state.sim.signals.values().collect();
+        signals.sort_by(|a, b| a.arrival.total_cmp(&b.arrival));
+        for signal in signals.iter().take(ROWS) {
+            let edge = &state.sim.edges[signal.cur_edge];
+            let eta = signal.arrival - state.sim.time;
+            let eta = if eta.is_finite() {
+                format!("{eta:.2}s")
+            } else {
+                "stalled".to_string()
+            };
+            ui.label(
+                None,
+                &format!(
+                    "{}>{} {:.2}s eta {eta}",
+                    name(edge.nodes.0),
+                    name(edge.nodes.1),
+                    signal.start_time,
+                ),
+            );
+        }
+        if signals.len() > ROWS {
+            ui.label(None, &format!("{} more", signals.len() - ROWS));
+        }
+    });
+}
+
+fn node_inspector(ui: &mut Ui, node: &mut Node, time: f32, quantum: f32) {
+    let mut mode = match node.merge {
+        Merge::Off => 0,
+        Merge::Simultaneous => 1,
+        Merge::Window(_) => 2,
+    };
+    ui.combo_box(hash!(), "merge", MERGE_MODES, &mut mode);
+    node.merge = match mode {
+        0 => Merge::Off,
+        1 => Merge::Simultaneous,
+        _ => {
+            let mut bars = match node.merge {
+                Merge::Window(bars) => bars,
+                _ => 0.25,
+            };
+            ui.slider(hash!(), "window (bars)", 0.0..1.0, &mut bars);
+            Merge::Window(bars)
+        }
+    };
+    ui.checkbox(hash!(), "merge downstream", &mut node.merge_downstream);
+    let mut relaunch = false;
+    match &mut node.kind {
+        NodeKind::Spawner {
+            bar_delay,
+            phase,
+            payload,
+            ..
+        } => {
+            ui.drag(hash!(), "every (bars)", (0.25, 64.0), bar_delay);
+            relaunch = phase_drag(ui, phase);
+            payload_inspector(ui, payload);
+        }
+        NodeKind::Transform(Transform::Velocity(factor)) => {
+            ui.slider(hash!(), "velocity x", 0.0..2.0, factor)
+        }
+        NodeKind::Transform(Transform::Transpose(semitones)) => {
+            semitone_drag(ui, "semitones", semitones)
+        }
+        NodeKind::Transform(Transform::Tag(tag)) => tag_input(ui, tag),
+        NodeKind::Euclid(euclid) => {
+            ui.drag(hash!(), "pulses", (0, 64), &mut euclid.pulses);
+            ui.drag(hash!(), "steps", (1, 64), &mut euclid.steps);
+            ui.drag(hash!(), "rotation", (0, 64), &mut euclid.rotation);
+            ui.drag(
+                hash!(),
+                "cycle (bars)",
+                (0.25, 64.0),
+                &mut euclid.cycle_bars,
+            );
+            euclid.step %= euclid.steps.max(1);
+            relaunch = phase_drag(ui, &mut euclid.phase);
+            payload_inspector(ui, &mut euclid.payload);
+        }
+        NodeKind::Pattern(pattern) => {
+            let mut len = pattern.steps.len() as u32;
+            ui.drag(hash!(), "steps", (1, 64), &mut len);
+            pattern.steps.resize(len.max(1) as usize, Step::Off);
+            ui.drag(
+                hash!(),
+                "cycle (bars)",
+                (0.25, 64.0),
+                &mut pattern.cycle_bars,
+            );
+            relaunch = phase_drag(ui, &mut pattern.phase);
+            payload_inspector(ui, &mut pattern.payload);
+        }
+        NodeKind::Trigger(trigger) => {
+            let names: Vec<String> = TRIGGER_KEYS
+                .iter()
+                .map(|(key, _)| key.to_string())
+                .collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let mut idx = TRIGGER_KEYS
+                .iter()
+                .position(|&(key, _)| key == trigger.key)
+                .unwrap_or(0);
+            ui.combo_box(hash!(), "key", &names, &mut idx);
+            trigger.key = TRIGGER_KEYS[idx].0;
+            ui.checkbox(hash!(), "quantize to beat", &mut trigger.quantize);
+            payload_inspector(ui, &mut trigger.payload);
+        }
+        NodeKind::Switch { open, key } => {
+            ui.checkbox(hash!(), "open", open);
+            let names: Vec<String> = std::iter::once("none".to_string())
+                .chain(TRIGGER_KEYS.iter().map(|(key, _)| key.to_string()))
+                .collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let mut idx = TRIGGER_KEYS
+                .iter()
+                .position(|&(k, _)| Some(k) == *key)
+                .map_or(0, |i| i + 1);
+            ui.combo_box(hash!(), "key", &names, &mut idx);
+            *key = idx.checked_sub(1).map(|i| TRIGGER_KEYS[i].0);
+        }
+        NodeKind::Script(script) => {
+            let old = script.source.clone();
+            ui.input_text(hash!(), "script", &mut script.source);
+            if script.source != old {
+                script.recompile();
+            }
+            ui.label(None, &format!("arrivals: {}", script.count));
+            match (&script.program, &script.error) {
+                (Err(e), _) | (_, Some(e)) => ui.label(None, &format!("error: {e}")),
+                _ => ui.label(None, "ok"),
+            }
+            ui.label(None, "vars: bar beat count vel transpose");
+            ui.label(None, "      tag outs time");
+        }
+        _ => {}
+    }
+    if relaunch {
+        node.kind.launch(time, quantum);
+    }
+}
+
+/// Returns true if the phase was changed.
+fn phase_drag(ui: &mut Ui, phase: &mut f32) -> bool {
+    let old = *phase;
+    ui.drag(hash!(), "phase (bars)", (0.0, 16.0), phase);
+    *phase != old
+}
+
+fn payload_inspector(ui: &mut Ui, payload: &mut Payload) {
+    ui.slider(hash!(), "velocity", 0.0..2.0, &mut payload.velocity);
+    semitone_drag(ui, "transpose", &mut payload.transpose);
+    tag_input(ui, &mut payload.tag);
+}
+
+fn semitone_drag(ui: &mut Ui, label: &str, semitones: &mut i32) {
+    let mut value = *semitones as f32;
+    ui.drag(hash!(), label, (-24.0, 24.0), &mut value);
+    *semitones = value.round() as i32;
+}
+
+fn tag_input(ui: &mut Ui, tag: &mut Option<char>) {
+    let mut text = tag.map(String::from).unwrap_or_default();
+    ui.input_text(hash!(), "tag", &mut text);
+    *tag = text.chars().last();
+}
+
+/// `macros` tells which ends of the edge are macro nodes and so have ports.
+fn edge_inspector(ui: &mut Ui, edge: &mut Edge, macros: (bool, bool)) {
+    let (mut kind, mut bars) = match edge.kind {
+        EdgeKind::Forward => (0, 1.0),
+        EdgeKind::Loop { bars } => (1, bars),
+        EdgeKind::Wrap { bars } => (2, bars),
+    };
+    ui.combo_box(hash!(), "kind", EDGE_KINDS, &mut kind);
+    if kind != 0 {
+        ui.drag(hash!(), "bars", (0.0, 64.0), &mut bars);
+    }
+    edge.kind = match kind {
+        0 => EdgeKind::Forward,
+        1 => EdgeKind::Loop { bars },
+        _ => EdgeKind::Wrap { bars },
+    };
+    ui.checkbox(hash!(), "enabled", &mut edge.enabled);
+    ui.drag(hash!(), "delay (bars)", (0.0, 16.0), &mut edge.delay);
+    ui.slider(hash!(), "probability", 0.0..1.0, &mut edge.probability);
+    ui.input_text(hash!(), "label", &mut edge.label);
+    let port_drag = |ui: &mut Ui, label: &str, port: &mut usize| {
+        let mut value = *port as u32;
+        ui.drag(hash!(label), label, (0, 64), &mut value);
+        *port = value as usize;
+    };
+    if macros.0 {
+        port_drag(ui, "from outlet", &mut edge.ports.0);
+    }
+    if macros.1 {
+        port_drag(ui, "to inlet", &mut edge.ports.1);
+    }
+}
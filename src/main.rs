@@ -1,128 +1,214 @@
 use macroquad::prelude::*;
 use macroquad::ui;
+mod analysis;
 mod audio;
 mod edge;
+mod inspector;
+mod nesting;
 mod particle;
+mod period;
+mod predict;
+mod record;
+mod scene;
+mod script;
+mod subpatch;
 mod translation;
 use edge::*;
 use particle::{spawn_particles, Particle};
-use slotmap::{new_key_type, SecondaryMap, SlotMap};
+use slotmap::new_key_type;
 
+use analysis::{analyze, Analysis, Warning};
 use audio::*;
 
 new_key_type! {struct EdgeId; }
 new_key_type! {struct NodeId; }
+new_key_type! {struct SignalId; }
 mod util;
 use util::IndexOf as _;
 
 mod render;
+mod rng;
+mod sim;
+use inspector::{
+    draw_arrangement, draw_debugger, draw_inspector, draw_preview_panel, draw_recorder,
+    draw_scene_panel, draw_signal_panel, draw_step_editor, draw_transport, draw_view_panel,
+};
 use render::draw;
 mod node;
 use node::*;
+use predict::{difference, Preview};
+use record::Action;
+use rng::Rng;
+use scene::Section;
+use script::Script;
+use sim::*;
 
 const NODE_RADIUS: f32 = 14.0;
 const PX_PER_BAR: f32 = 150.0;
 const BAR_TIME: f32 = 1.0;
 const SIGNAL_SPEED: f32 = PX_PER_BAR / BAR_TIME;
-
-struct Signal {
-    cur_edge: EdgeId,
-    start_time: f32,
-}
-
-#[derive(Default)]
-struct Adjlist {
-    incoming: Vec<(NodeId, EdgeId)>,
-    outgoing: Vec<(NodeId, EdgeId)>,
-}
+const BEATS_PER_BAR: u32 = 4;
+const SIGNAL_WARNING_TIME: f32 = 2.0;
+const TICKS_PER_BEAT: u32 = 48;
+const TICK_TIME: f32 = BAR_TIME / (BEATS_PER_BAR * TICKS_PER_BEAT) as f32;
+/// Longest stretch the simulation catches up on after a stalled frame.
+const MAX_CATCH_UP: f32 = 8.0 * BAR_TIME;
+/// Samples caught up on later than this are skipped rather than played in a burst.
+const MAX_LATENESS: f32 = BAR_TIME / BEATS_PER_BAR as f32;
+/// Keys trigger nodes can be bound to, leaving out the editor shortcuts.
+const TRIGGER_KEYS: [(char, KeyCode); 28] = [
+    ('Q', KeyCode::Q),
+    ('E', KeyCode::E),
+    ('R', KeyCode::R),
+    ('T', KeyCode::T),
+    ('Y', KeyCode::Y),
+    ('U', KeyCode::U),
+    ('I', KeyCode::I),
+    ('O', KeyCode::O),
+    ('P', KeyCode::P),
+    ('F', KeyCode::F),
+    ('H', KeyCode::H),
+    ('J', KeyCode::J),
+    ('L', KeyCode::L),
+    ('Z', KeyCode::Z),
+    ('C', KeyCode::C),
+    ('V', KeyCode::V),
+    ('B', KeyCode::B),
+    ('N', KeyCode::N),
+    ('M', KeyCode::M),
+    ('2', KeyCode::Key2),
+    ('3', KeyCode::Key3),
+    ('4', KeyCode::Key4),
+    ('5', KeyCode::Key5),
+    ('6', KeyCode::Key6),
+    ('7', KeyCode::Key7),
+    ('8', KeyCode::Key8),
+    ('9', KeyCode::Key9),
+    ('0', KeyCode::Key0),
+];
 
 #[derive(Default)]
 struct State {
-    nodes: SlotMap<NodeId, Node>,
-    edges: SlotMap<EdgeId, Edge>,
-    adj: SecondaryMap<NodeId, Adjlist>,
-
-    signals: Vec<Signal>,
+    sim: Simulation,
     particles: Vec<Particle>,
     camera_pos: Vec2,
     mouse_pos: Vec2,
-    time: f32,
     dt: f32,
     mode: Mode,
-    paused: bool,
     hovered_node: Option<NodeId>,
+    selection: Option<Selection>,
+    view: Option<NodeId>,
+    group: Vec<NodeId>,
+    over_ui: bool,
+    /// Random stream for visuals only.
+    fx_rng: Rng,
+    analysis: Analysis,
+    preview: Preview,
 }
+#[derive(Clone, Copy, PartialEq)]
 enum Selection {
     Node(NodeId),
     Edge(EdgeId),
 }
 impl Selection {
     fn get_center(&self, state: &State) -> Vec2 {
-        match self {
-            &Self::Node(node) => state.nodes[node].pos,
-            &Self::Edge(edge) => {
-                let (u, v) = state.edges[edge].nodes;
-                (state.nodes[u].pos + state.nodes[v].pos) / 2.0
-            }
+        match *self {
+            Self::Node(node) => state.sim.nodes[node].pos,
+            Self::Edge(edge) => state.sim.edges[edge].point_at(&state.sim.nodes, 0.5),
         }
     }
 }
 
 enum Mode {
-    Base { selected_node: Option<NodeId> },
-    AddEdge { first: Option<NodeId> },
+    Base {
+        selected_node: Option<NodeId>,
+    },
+    AddEdge {
+        first: Option<NodeId>,
+    },
     Delete(Option<Selection>),
-    UpdNode { kind: NodeKind },
+    UpdNode {
+        kind: NodeKind,
+    },
+    /// Clicking triggers and switches plays them instead of editing.
+    Perform,
 }
 impl Default for Mode {
     fn default() -> Self {
-        return Self::Base {
+        Self::Base {
             selected_node: None,
-        };
+        }
     }
 }
 impl State {
-    fn add_node(&mut self, node: Node) -> NodeId {
-        let id = self.nodes.insert(node);
-        self.adj.insert(id, Default::default());
-        id
+    fn remove_node(&mut self, node: NodeId) {
+        self.sim.remove_node(node);
+        self.group.retain(|&id| self.sim.nodes.contains_key(id));
+        self.selection = None;
     }
-    fn add_edge(&mut self, edge: Edge) -> EdgeId {
-        let (u, v) = edge.nodes;
-        let id = self.edges.insert(edge);
-        self.adj[u].outgoing.push((v, id));
-        self.adj[v].incoming.push((u, id));
-        id
+    fn remove_edge(&mut self, edge: EdgeId) -> Edge {
+        if self.selection == Some(Selection::Edge(edge)) {
+            self.selection = None;
+        }
+        self.sim.remove_edge(edge)
     }
-    fn remove_node(&mut self, node: NodeId) {
-        let adjlist = self.adj.remove(node).unwrap();
-        for (u, edge) in adjlist.incoming {
-            let pos = self.adj[u].outgoing.index_of(&(node, edge)).unwrap();
-            self.adj[u].outgoing.swap_remove(pos);
-            self.edges.remove(edge);
+    fn stop(&mut self) {
+        self.sim.stop();
+        self.particles.clear();
+    }
+    fn rewind(&mut self) {
+        // a take can't jump back in time, rewinding ends it
+        self.sim.recorder.recording = false;
+        self.sim.recorder.replay = None;
+        self.sim.rewind();
+        self.particles.clear();
+    }
+    /// First performance key no node is bound to yet.
+    fn free_key(&self) -> char {
+        let used: Vec<char> = self
+            .sim
+            .nodes
+            .values()
+            .filter_map(|node| node.kind.key())
+            .collect();
+        TRIGGER_KEYS
+            .iter()
+            .map(|&(key, _)| key)
+            .find(|key| !used.contains(key))
+            .unwrap_or(TRIGGER_KEYS[0].0)
+    }
+    fn get_selection(&self, pos: Vec2, max_dist: f32) -> Option<Selection> {
+        let mut min_dist = f32::INFINITY;
+        let mut sel = None;
+        for (id, node) in &self.sim.nodes {
+            let d = node.pos.distance_squared(pos);
+            if d < min_dist && self.in_view(id) {
+                sel = Some(Selection::Node(id));
+                min_dist = d;
+            }
         }
-
-        for (u, edge) in adjlist.outgoing {
-            let pos = self.adj[u].incoming.index_of(&(node, edge)).unwrap();
-            self.adj[u].incoming.swap_remove(pos);
-            self.edges.remove(edge);
+        let node_dist = min_dist;
+        for (id, edge) in &self.sim.edges {
+            if !self.in_view(edge.nodes.0) {
+                continue;
+            }
+            let d = edge.distance_squared(pos, &self.sim.nodes, 0.5);
+            if d < 4.0 * node_dist && d < min_dist {
+                min_dist = d;
+                sel = Some(Selection::Edge(id));
+            }
         }
-
-        self.nodes.remove(node);
-    }
-    fn remove_edge(&mut self, edge: EdgeId) {
-        let (u, v) = self.edges.remove(edge).unwrap().nodes;
-        let idx = self.adj[u].outgoing.index_of(&(v, edge)).unwrap();
-        self.adj[u].outgoing.swap_remove(idx);
-        let idx = self.adj[v].incoming.index_of(&(u, edge)).unwrap();
-        self.adj[v].incoming.swap_remove(idx);
+        if min_dist > max_dist * max_dist {
+            return None;
+        }
+        sel
     }
     fn get_closest_node(&self, pos: Vec2) -> Option<(NodeId, f32)> {
         let mut min_dist = f32::INFINITY;
         let mut closest = None;
-        for (id, node) in &self.nodes {
+        for (id, node) in &self.sim.nodes {
             let d = node.pos.distance_squared(pos);
-            if d < min_dist {
+            if d < min_dist && self.in_view(id) {
                 closest = Some(id);
                 min_dist = d;
             }
@@ -132,6 +218,10 @@ impl State {
 }
 
 fn handle_input(state: &mut State, skip_mouse: bool) {
+    // keys typed into panels shouldn't trigger shortcuts
+    let over_ui = state.over_ui;
+    let is_key_down = |key| !over_ui && is_key_down(key);
+    let is_key_pressed = |key| !over_ui && is_key_pressed(key);
     let cam_speed = 150.0;
     if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
         state.camera_pos.x -= cam_speed * state.dt;
@@ -146,9 +236,22 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
         state.camera_pos.y += cam_speed * state.dt;
     }
     if is_key_pressed(KeyCode::Space) {
-        state.paused = !state.paused;
+        state.sim.perform(Action::Pause);
+    }
+    if is_key_pressed(KeyCode::Period) {
+        state.sim.single_step = true;
+    }
+    if is_key_pressed(KeyCode::Enter) {
+        state.stop();
+    }
+    if is_key_pressed(KeyCode::Home) {
+        state.rewind();
+    }
+    if is_key_pressed(KeyCode::K) {
+        state.sim.perform(Action::KillSignals);
     }
     if is_key_pressed(KeyCode::Escape) {
+        state.selection = None;
         state.mode = Mode::Base {
             selected_node: None,
         };
@@ -158,6 +261,22 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
             selected_node: None,
         };
     }
+    if is_key_pressed(KeyCode::G) && !state.group.is_empty() {
+        let group = std::mem::take(&mut state.group);
+        let id = state.sim.collapse(&group);
+        state.selection = Some(Selection::Node(id));
+    }
+    for (key, code) in TRIGGER_KEYS {
+        if is_key_pressed(code) {
+            state.sim.perform(Action::Key(key));
+        }
+    }
+    if is_key_pressed(KeyCode::Tab) {
+        state.mode = match state.mode {
+            Mode::Perform => Mode::default(),
+            _ => Mode::Perform,
+        };
+    }
     if is_key_pressed(KeyCode::X) {
         if matches!(state.mode, Mode::Delete(_)) {
             state.mode = Mode::Base {
@@ -169,15 +288,31 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
     }
     match &state.mode {
         Mode::Base { selected_node } => {
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
             if is_key_pressed(KeyCode::Key1) {
                 state.mode = Mode::AddEdge { first: None };
+            } else if shift && is_mouse_button_pressed(MouseButton::Left) && !skip_mouse {
+                if let Some(id) = state.hovered_node {
+                    match state.group.index_of(&id) {
+                        Some(idx) => {
+                            state.group.swap_remove(idx);
+                        }
+                        None => state.group.push(id),
+                    }
+                }
             } else if is_mouse_button_down(MouseButton::Left) && selected_node.is_none() {
-                for i in state.nodes.keys() {
-                    let node = &state.nodes[i];
-                    if node.pos.distance_squared(state.mouse_pos) <= NODE_RADIUS.powi(2) {
+                if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse {
+                    state.selection = state.get_selection(state.mouse_pos, 2.0 * NODE_RADIUS);
+                }
+                for i in state.sim.nodes.keys() {
+                    let node = &state.sim.nodes[i];
+                    if node.pos.distance_squared(state.mouse_pos) <= NODE_RADIUS.powi(2)
+                        && state.in_view(i)
+                    {
                         state.mode = Mode::Base {
                             selected_node: Some(i),
                         };
+                        state.selection = Some(Selection::Node(i));
                         break;
                     }
                 }
@@ -188,16 +323,17 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
                 };
             }
         }
-        Mode::Delete(sel) => {
-            if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse {
-                match sel {
-                    Some(Selection::Node(id)) => state.remove_node(*id),
-                    Some(Selection::Edge(id)) => state.remove_edge(*id),
-                    None => {}
+        Mode::Delete(sel) if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse => {
+            match *sel {
+                Some(Selection::Node(id)) => state.remove_node(id),
+                Some(Selection::Edge(id)) => {
+                    state.remove_edge(id);
                 }
-                state.mode = Mode::Delete(None)
+                None => {}
             }
+            state.mode = Mode::Delete(None)
         }
+        Mode::Delete(_) => {}
         Mode::AddEdge { first } => {
             if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse {
                 match *first {
@@ -206,10 +342,12 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
                         if let Some(h) = state.hovered_node {
                             id = h;
                         } else {
-                            id = state.add_node(Node::new(state.mouse_pos.x, state.mouse_pos.y));
+                            id = state.add_node_here(state.mouse_pos);
                         }
                         if id != first_id {
-                            state.add_edge(Edge::new(first_id, id));
+                            state
+                                .sim
+                                .add_edge(Edge::new_auto(first_id, id, &state.sim.nodes));
                             state.mode = Mode::AddEdge { first: Some(id) };
                         }
                     }
@@ -217,8 +355,7 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
                         if let Some(id) = state.hovered_node {
                             state.mode = Mode::AddEdge { first: Some(id) };
                         } else {
-                            let id =
-                                state.add_node(Node::new(state.mouse_pos.x, state.mouse_pos.y));
+                            let id = state.add_node_here(state.mouse_pos);
                             state.mode = Mode::AddEdge { first: Some(id) };
                         }
                     }
@@ -231,63 +368,86 @@ fn handle_input(state: &mut State, skip_mouse: bool) {
                 };
             }
         }
-        Mode::UpdNode { kind } => {
-            if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse {
-                if let Some(id) = state.hovered_node {
-                    state.nodes[id].kind = kind.clone();
-                } else {
-                    let kind = kind.clone();
-                    let new_node = state.add_node(Node::new(state.mouse_pos.x, state.mouse_pos.y));
-                    state.nodes[new_node].kind = kind;
-                }
+        Mode::UpdNode { kind } if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse => {
+            let mut kind = kind.clone();
+            kind.launch(state.sim.time, state.sim.launch_quantum);
+            if let NodeKind::Trigger(trigger) = &mut kind {
+                trigger.key = state.free_key();
+            }
+            if let Some(id) = state.hovered_node {
+                state.sim.nodes[id].kind = kind;
+                state.sim.edited = true;
+            } else {
+                let new_node = state.add_node_here(state.mouse_pos);
+                state.sim.nodes[new_node].kind = kind;
+            }
+        }
+        Mode::UpdNode { .. } => {}
+        Mode::Perform if is_mouse_button_pressed(MouseButton::Left) && !skip_mouse => {
+            if let Some(id) = state.hovered_node {
+                state.sim.perform(Action::Press(id));
             }
         }
-        _ => {}
+        Mode::Perform => {}
+    }
+}
+/// Re-predicts the schedule after edits or a change of length.
+fn update_preview(state: &mut State) {
+    let preview = &mut state.preview;
+    if !preview.show {
+        preview.made_for = None;
+        return;
+    }
+    if state.sim.edited || preview.made_for != Some(preview.bars) {
+        preview.schedule = state.sim.predict_from_top(preview.bars as f32);
+        preview.made_for = Some(preview.bars);
+        state.sim.edited = false;
     }
 }
 #[macroquad::main("moi")]
 async fn main() {
-    rand::srand(macroquad::miniquad::date::now() as _);
-    println!("{}", rand::gen_range(0, 100));
-    let mut state = State::default();
-    let audio_system = Audio::new(load_samples().await);
-    let ids = vec![
-        state.add_node(Node::new(10.0, 10.0)),
-        state.add_node(Node::new(50.0, 200.0)),
-        state.add_node(Node::new(100.0, 100.0)),
-        state.add_node(Node::new(200.0, 250.0)),
-        state.add_node(Node::new(300.0, 200.0)),
-        state.add_node(Node::new(-50.0, -50.0)),
+    let clock = macroquad::miniquad::date::now().to_bits();
+    let mut state = State {
+        sim: Simulation::new(),
+        fx_rng: Rng::new(clock.rotate_left(32)),
+        ..Default::default()
+    };
+    state.sim.reseed(clock);
+    state.preview.bars = 4;
+    state.preview.loop_bars = 4.0;
+    let mut audio_system = Audio::new(load_samples().await);
+    let ids = [
+        state.sim.add_node(Node::new(10.0, 10.0)),
+        state.sim.add_node(Node::new(50.0, 200.0)),
+        state.sim.add_node(Node::new(100.0, 100.0)),
+        state.sim.add_node(Node::new(200.0, 250.0)),
+        state.sim.add_node(Node::new(300.0, 200.0)),
+        state.sim.add_node(Node::new(-50.0, -50.0)),
     ];
 
-    state.add_edge(Edge::new(ids[0], ids[1]));
-    state.add_edge(Edge::new(ids[1], ids[2]));
-    state.add_edge(Edge::new(ids[2], ids[3]));
-    state.add_edge(Edge::new(ids[0], ids[4]));
-    state.add_edge(Edge::new(ids[1], ids[4]));
-    state.add_edge(Edge::new(ids[2], ids[4]));
-    state.add_edge(Edge::new(ids[5], ids[0]));
+    state.sim.add_edge(Edge::new(ids[0], ids[1]));
+    state.sim.add_edge(Edge::new(ids[1], ids[2]));
+    state.sim.add_edge(Edge::new(ids[2], ids[3]));
+    state.sim.add_edge(Edge::new(ids[0], ids[4]));
+    state.sim.add_edge(Edge::new(ids[1], ids[4]));
+    state.sim.add_edge(Edge::new(ids[2], ids[4]));
+    state.sim.add_edge(Edge::new(ids[5], ids[0]));
     state.mode = Mode::Base {
         selected_node: None,
     };
-    state.nodes[ids[5]].kind = NodeKind::Spawner {
-        bar_delay: 4.0,
-        next_spawn: 0.0,
-    };
-    let mut next_time_spawn = 0.0;
+    state.sim.nodes[ids[5]].kind = NodeKind::spawner(4.0);
 
     // let mut nodes = vec![Node::new(10.0, 10.0), Node::new(100.0, 100.0)];
     // let edges = vec![Edge::new(0, 1)];
     loop {
-        let mut dt = get_frame_time();
+        let frame_time = get_frame_time();
+        // only camera and particles skip long frames, the simulation catches up
+        let mut dt = frame_time;
         let m_pos = Vec2::from(mouse_position());
         state.mouse_pos = m_pos + state.camera_pos;
         if dt >= 0.5 {
             dt = 0.0;
         }
-        if !state.paused {
-            state.time += dt;
-        };
         state.dt = dt;
         if let Some((id, _)) = state
             .get_closest_node(state.mouse_pos)
@@ -297,108 +457,60 @@ async fn main() {
         } else {
             state.hovered_node = None;
         }
-        for (id, node) in &mut state.nodes {
-            if let NodeKind::Spawner {
-                bar_delay,
-                next_spawn,
-            } = &mut node.kind
-            {
-                if *next_spawn <= state.time {
-                    for (_, edge_id) in &state.adj[id].outgoing {
-                        state.signals.push(Signal {
-                            cur_edge: *edge_id,
-                            start_time: *next_spawn,
-                        })
-                    }
-                    *next_spawn += *bar_delay * BAR_TIME;
-                    next_time_spawn = *next_spawn;
-                }
-            }
-        }
         match &state.mode {
-            Mode::Base { selected_node } => {
-                if let Some(v) = selected_node {
-                    state.nodes[*v].pos = state.mouse_pos;
-                }
+            Mode::Base {
+                selected_node: Some(v),
+            } => {
+                state.sim.nodes[*v].pos = state.mouse_pos;
+                state.sim.reschedule(*v);
             }
             Mode::Delete(_) => {
-                let mut min_dist = f32::INFINITY;
-                let mut new_sel = None;
-                for (id, node) in &state.nodes {
-                    let d = node.pos.distance_squared(state.mouse_pos);
-                    if d < min_dist {
-                        new_sel = Some(Selection::Node(id));
-                        min_dist = d;
-                    }
-                }
-                let node_dist = min_dist;
-                for (id, edge) in &state.edges {
-                    let d = edge.distance_squared(state.mouse_pos, &state.nodes, 0.5);
-                    if d < 4.0 * node_dist && d < min_dist {
-                        min_dist = d;
-                        new_sel = Some(Selection::Edge(id));
-                    }
-                }
-                if min_dist > 100.0 * 100.0 {
-                    new_sel = None;
-                }
-                state.mode = Mode::Delete(new_sel);
+                state.mode = Mode::Delete(state.get_selection(state.mouse_pos, 100.0));
             }
 
             _ => {}
         }
+        if let Mode::UpdNode { kind } = &mut state.mode {
+            kind.launch(state.sim.time, state.sim.launch_quantum);
+        }
 
-        for i in (0..state.signals.len()).rev() {
-            if !state.edges.contains_key(state.signals[i].cur_edge) {
-                state.signals.swap_remove(i);
-                continue;
-            }
-            let (u, v) = state.edges[state.signals[i].cur_edge].nodes;
-            let dx = state.nodes[v].pos.x - state.nodes[u].pos.x;
-            if dx < 0.0 {
-                state.signals[i].start_time += dt;
-                continue;
-            }
-            let t = state.time - state.signals[i].start_time;
-
-            if t * SIGNAL_SPEED < dx {
-                continue;
-            }
-            state.signals[i].start_time += dx / SIGNAL_SPEED;
-            // let next = state.adj_list[v].choose();
-            // println!("{}", state.adj_list[v].len());
-            let start_time = state.signals[i].start_time;
-            let cur = state.signals[i].cur_edge;
-            let to = state.edges[cur].nodes.1;
-            match state.nodes[to].kind {
-                NodeKind::Sample(idx) => audio_system.play(idx),
-                _ => {}
-            }
-            let pos = state.nodes[to].pos;
-            spawn_particles(&mut state, pos);
-            for &(_, e) in &state.adj[to].outgoing {
-                state.signals.push({
-                    Signal {
-                        start_time,
-                        cur_edge: e,
-                    }
-                });
+        for event in state.sim.step(frame_time) {
+            let Node { pos, parent, .. } = state.sim.nodes[event.node];
+            spawn_particles(&mut state, pos, parent, event.time);
+            let late = state.sim.time - event.time > MAX_LATENESS;
+            if let Some(sample) = event.sample.filter(|_| !late) {
+                let Payload {
+                    velocity,
+                    transpose,
+                    ..
+                } = event.payload;
+                audio_system.play(sample, velocity, transpose).await;
             }
-
-            state.signals.swap_remove(i);
         }
         for i in (0..state.particles.len()).rev() {
-            if state.particles[i].end_time <= state.time {
+            if state.particles[i].end_time <= state.sim.time {
                 state.particles.swap_remove(i);
             } else {
-                if !state.paused {
+                if !state.sim.paused {
                     state.particles[i].update(dt);
                 }
             }
         }
+        state.analysis = analyze(&state.sim);
         clear_background(BLACK);
         draw(&state);
-        let mut skip_mouse = false;
+        draw_inspector(&mut state);
+        draw_step_editor(&mut state);
+        draw_signal_panel(&mut state);
+        draw_debugger(&mut state);
+        draw_transport(&mut state);
+        draw_view_panel(&mut state);
+        draw_scene_panel(&mut state);
+        draw_arrangement(&mut state);
+        draw_recorder(&mut state);
+        draw_preview_panel(&mut state);
+        state.over_ui = ui::root_ui().is_mouse_over(m_pos);
+        let mut skip_mouse = state.over_ui;
         // ui::root_ui().push_skin(&ui::Skin {
         //     ..skin.clone()
 
@@ -408,7 +520,20 @@ async fn main() {
                 state.mode = Mode::UpdNode {
                     kind: NodeKind::Sample(i),
                 };
-                audio_system.play(i);
+                audio_system.play(i, 1.0, 0).await;
+                skip_mouse = true;
+            }
+        }
+        ui::root_ui().separator();
+        for (name, transform) in [
+            ("Vel", Transform::Velocity(0.7)),
+            ("Pitch", Transform::Transpose(2)),
+            ("Tag", Transform::Tag(Some('a'))),
+        ] {
+            if ui::root_ui().button(None, name) {
+                state.mode = Mode::UpdNode {
+                    kind: NodeKind::Transform(transform),
+                };
                 skip_mouse = true;
             }
         }
@@ -416,10 +541,7 @@ async fn main() {
         let mut spawner = |i: usize| {
             if ui::root_ui().button(None, "S".to_string() + &i.to_string()) {
                 state.mode = Mode::UpdNode {
-                    kind: NodeKind::Spawner {
-                        bar_delay: i as f32,
-                        next_spawn: state.time.max(next_time_spawn),
-                    },
+                    kind: NodeKind::spawner(i as f32),
                 };
                 skip_mouse = true;
             }
@@ -429,7 +551,41 @@ async fn main() {
         spawner(3);
         spawner(4);
         spawner(8);
+        if ui::root_ui().button(None, "E") {
+            state.mode = Mode::UpdNode {
+                kind: NodeKind::Euclid(Euclid::new(3, 8)),
+            };
+            skip_mouse = true;
+        }
+        if ui::root_ui().button(None, "P") {
+            state.mode = Mode::UpdNode {
+                kind: NodeKind::Pattern(Pattern::new(16)),
+            };
+            skip_mouse = true;
+        }
+        if ui::root_ui().button(None, "T") {
+            state.mode = Mode::UpdNode {
+                kind: NodeKind::Trigger(Trigger::new(TRIGGER_KEYS[0].0)),
+            };
+            skip_mouse = true;
+        }
+        if ui::root_ui().button(None, "Sw") {
+            state.mode = Mode::UpdNode {
+                kind: NodeKind::Switch {
+                    open: true,
+                    key: None,
+                },
+            };
+            skip_mouse = true;
+        }
+        if ui::root_ui().button(None, "?") {
+            state.mode = Mode::UpdNode {
+                kind: NodeKind::Script(Script::new("count % outs")),
+            };
+            skip_mouse = true;
+        }
         handle_input(&mut state, skip_mouse);
+        update_preview(&mut state);
 
         next_frame().await;
     }
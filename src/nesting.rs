@@ -0,0 +1,282 @@
+use glam::{vec2, Vec2};
+use slotmap::SecondaryMap;
+
+use crate::edge::{Edge, EdgeKind};
+use crate::node::{Node, NodeKind};
+use crate::sim::Simulation;
+use crate::{EdgeId, NodeId};
+
+const OUTLET_OFFSET: f32 = 40.0;
+
+impl Simulation {
+    /// All nodes nested inside `id`, parents before their children.
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = vec![id];
+        let mut i = 0;
+        while i < out.len() {
+            let parent = out[i];
+            out.extend(
+                self.nodes
+                    .iter()
+                    .filter(|(_, node)| node.parent == Some(parent))
+                    .map(|(child, _)| child),
+            );
+            i += 1;
+        }
+        out.remove(0);
+        out
+    }
+    /// Edges a signal continues on after arriving at `to` through `via`,
+    /// passing straight through macro boundaries. Sorted by key, so scripts
+    /// and the random stream see the same order on every run.
+    pub fn next_edges(&self, to: NodeId, via: EdgeId) -> Vec<EdgeId> {
+        let outgoing = |id: NodeId| -> Vec<EdgeId> {
+            let mut out: Vec<EdgeId> = self
+                .adj
+                .get(id)
+                .map_or(vec![], |adj| adj.outgoing.iter().map(|&(_, e)| e).collect());
+            out.sort();
+            out
+        };
+        match &self.nodes[to].kind {
+            NodeKind::Macro { inlets, .. } => inlets
+                .get(self.edges[via].ports.1)
+                .map_or(vec![], |&inlet| outgoing(inlet)),
+            NodeKind::Outlet(port) => self.nodes[to].parent.map_or(vec![], |m| {
+                outgoing(m)
+                    .into_iter()
+                    .filter(|&e| self.edges[e].ports.0 == *port)
+                    .collect()
+            }),
+            _ => outgoing(to),
+        }
+    }
+    /// Moves `group` into a new macro node, rewiring edges that cross the
+    /// group boundary through inlets and outlets so that timing is unchanged.
+    pub fn collapse(&mut self, group: &[NodeId]) -> NodeId {
+        let center = group.iter().map(|&id| self.nodes[id].pos).sum::<Vec2>() / group.len() as f32;
+        let m = self.add_node(Node {
+            kind: NodeKind::Macro {
+                inlets: vec![],
+                outlets: vec![],
+            },
+            parent: self.nodes[group[0]].parent,
+            ..Node::new(center.x, center.y)
+        });
+        let crossing: Vec<EdgeId> = self
+            .edges
+            .iter()
+            .filter(|(_, e)| group.contains(&e.nodes.0) != group.contains(&e.nodes.1))
+            .map(|(id, _)| id)
+            .collect();
+        for &id in group {
+            self.nodes[id].parent = Some(m);
+            self.nodes[id].pos -= center;
+        }
+        for id in crossing {
+            let mut edge = self.remove_edge(id);
+            let (u, v) = edge.nodes;
+            let NodeKind::Macro { inlets, outlets } = &self.nodes[m].kind else {
+                unreachable!()
+            };
+            let inward = group.contains(&v);
+            let k = if inward { inlets.len() } else { outlets.len() };
+            let mut port = Node::new(0.0, 0.0);
+            port.parent = Some(m);
+            let source_x = Edge::anchor_x(&self.nodes, u, edge.ports.0, true);
+            if inward {
+                port.pos = vec2(source_x - center.x, self.nodes[v].pos.y);
+                port.kind = NodeKind::Inlet(k);
+            } else {
+                port.pos = vec2(source_x, self.nodes[u].pos.y + OUTLET_OFFSET);
+                port.kind = NodeKind::Outlet(k);
+            }
+            let port = self.add_node(port);
+            let mut inner = edge.clone();
+            inner.kind = EdgeKind::Forward;
+            // the port sits level with the outside node, so the segment
+            // inside the macro keeps the edge's timing on the way in and
+            // the one outside keeps it on the way out
+            if inward {
+                inner.nodes.0 = port;
+                inner.ports.0 = 0;
+                inner.kind = edge.kind;
+                edge.nodes.1 = m;
+                edge.ports.1 = k;
+                edge.kind = EdgeKind::Forward;
+            } else {
+                inner.nodes.1 = port;
+                inner.ports.1 = 0;
+                edge.nodes.0 = m;
+                edge.ports.0 = k;
+            }
+            inner.enabled = true;
+            inner.delay = 0.0;
+            inner.probability = 1.0;
+            inner.label.clear();
+            self.add_edge(inner);
+            self.add_edge(edge);
+            if let NodeKind::Macro { inlets, outlets } = &mut self.nodes[m].kind {
+                if inward { inlets } else { outlets }.push(port);
+            }
+        }
+        m
+    }
+    /// Creates another instance of macro `id`, with its own copy of the interior.
+    pub fn duplicate(&mut self, id: NodeId, offset: Vec2) -> NodeId {
+        let mut map = SecondaryMap::new();
+        let mut copy = self.nodes[id].clone();
+        copy.pos += offset;
+        map.insert(id, self.add_node(copy));
+        let inner = self.descendants(id);
+        for &old in &inner {
+            let mut copy = self.nodes[old].clone();
+            copy.parent = copy.parent.map(|p| map[p]);
+            map.insert(old, self.add_node(copy));
+        }
+        for &old in inner.iter().chain([&id]) {
+            let new = map[old];
+            self.nodes[new].last_fire = f32::NEG_INFINITY;
+            if let NodeKind::Macro { inlets, outlets } = &mut self.nodes[new].kind {
+                for port in inlets.iter_mut().chain(outlets.iter_mut()) {
+                    if let Some(&p) = map.get(*port) {
+                        *port = p;
+                    }
+                }
+            }
+        }
+        let edges: Vec<EdgeId> = self
+            .edges
+            .iter()
+            .filter(|(_, e)| inner.contains(&e.nodes.0) && inner.contains(&e.nodes.1))
+            .map(|(e, _)| e)
+            .collect();
+        for e in edges {
+            let mut edge = self.edges[e].clone();
+            edge.nodes = (map[edge.nodes.0], map[edge.nodes.1]);
+            self.add_edge(edge);
+        }
+        map[id]
+    }
+    /// Takes inlet or outlet `id` out of its macro and renumbers the ports
+    /// after it. Edges on the macro that used the port are removed.
+    pub fn remove_port(&mut self, id: NodeId) {
+        let inward = match self.nodes[id].kind {
+            NodeKind::Inlet(_) => true,
+            NodeKind::Outlet(_) => false,
+            _ => return,
+        };
+        let Some(m) = self.nodes[id].parent else {
+            return;
+        };
+        let NodeKind::Macro { inlets, outlets } = &mut self.nodes[m].kind else {
+            return;
+        };
+        let ports = if inward { inlets } else { outlets };
+        let Some(k) = ports.iter().position(|&p| p == id) else {
+            return;
+        };
+        ports.remove(k);
+        let ports = ports.clone();
+        for (i, &p) in ports.iter().enumerate().skip(k) {
+            if let NodeKind::Inlet(n) | NodeKind::Outlet(n) = &mut self.nodes[p].kind {
+                *n = i;
+            }
+        }
+        let port = |edge: &Edge| {
+            if inward {
+                (edge.nodes.1 == m).then_some(edge.ports.1)
+            } else {
+                (edge.nodes.0 == m).then_some(edge.ports.0)
+            }
+        };
+        let stale: Vec<EdgeId> = self
+            .edges
+            .iter()
+            .filter(|(_, edge)| port(edge) == Some(k))
+            .map(|(e, _)| e)
+            .collect();
+        for e in stale {
+            self.remove_edge(e);
+        }
+        for edge in self.edges.values_mut() {
+            if port(edge).is_some_and(|p| p > k) {
+                if inward {
+                    edge.ports.1 -= 1;
+                } else {
+                    edge.ports.0 -= 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::analyze;
+    use crate::{BAR_TIME, PX_PER_BAR};
+
+    #[test]
+    fn removing_a_port_renumbers_the_rest() {
+        let mut sim = Simulation::new();
+        let mut node = |x: f32, y: f32| sim.add_node(Node::new(x * PX_PER_BAR, y));
+        let (a, d, b, c) = (
+            node(0.0, 0.0),
+            node(0.0, 50.0),
+            node(1.0, 0.0),
+            node(2.0, 0.0),
+        );
+        sim.add_edge(Edge::new(a, b));
+        sim.add_edge(Edge::new(d, b));
+        sim.add_edge(Edge::new(b, c));
+        let m = sim.collapse(&[b]);
+        let NodeKind::Macro { inlets, .. } = &sim.nodes[m].kind else {
+            unreachable!()
+        };
+        let (first, second) = (inlets[0], inlets[1]);
+        sim.remove_node(first);
+        let NodeKind::Macro { inlets, .. } = &sim.nodes[m].kind else {
+            unreachable!()
+        };
+        assert_eq!(inlets, &[second]);
+        assert!(matches!(sim.nodes[second].kind, NodeKind::Inlet(0)));
+        let into: Vec<&Edge> = sim.edges.values().filter(|e| e.nodes.1 == m).collect();
+        assert_eq!(into.len(), 1);
+        assert_eq!(into[0].nodes.0, d);
+        assert_eq!(into[0].ports.1, 0);
+        analyze(&sim);
+    }
+
+    #[test]
+    fn collapsing_keeps_loop_and_wrap_timing() {
+        let mut sim = Simulation::new();
+        let mut spawner = Node::new(PX_PER_BAR, 0.0);
+        spawner.kind = NodeKind::spawner(4.0);
+        let s = sim.add_node(spawner);
+        let b = sim.add_node(Node::new(0.5 * PX_PER_BAR, 0.0));
+        let c = sim.add_node(Node::new(3.0 * PX_PER_BAR, 50.0));
+        let wrap = sim.add_edge(Edge::new(s, b));
+        sim.edges[wrap].kind = EdgeKind::Wrap { bars: 1.0 };
+        let lap = sim.add_edge(Edge::new(s, c));
+        sim.edges[lap].kind = EdgeKind::Loop { bars: 1.25 };
+        let fired = |sim: &Simulation| -> Vec<(f32, NodeId)> {
+            let mut sim = sim.clone();
+            let mut fired = vec![];
+            while sim.time < 8.0 * BAR_TIME {
+                let events = sim.advance();
+                fired.extend(
+                    events
+                        .iter()
+                        .filter(|e| e.node == b || e.node == c)
+                        .map(|e| (e.time, e.node)),
+                );
+            }
+            fired
+        };
+        let before = fired(&sim);
+        assert_eq!(before.len(), 4);
+        sim.collapse(&[b, c]);
+        assert_eq!(before, fired(&sim));
+    }
+}
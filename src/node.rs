@@ -1,20 +1,361 @@
-use macroquad::math::{vec2, Vec2};
+use glam::{vec2, Vec2};
 
+use crate::script::Script;
+use crate::{NodeId, BAR_TIME, BEATS_PER_BAR};
+
+const SIMULTANEOUS_EPSILON: f32 = 1e-3;
+const UNACCENTED: f32 = 0.6;
+/// Shortest Euclid or pattern cycle, a zero cycle would never finish polling.
+const MIN_CYCLE_BARS: f32 = 1.0 / 64.0;
+
+#[derive(Clone, PartialEq)]
 pub struct Node {
     pub pos: Vec2,
     pub kind: NodeKind,
+    /// The macro node this node lives in, `None` for the top level.
+    pub parent: Option<NodeId>,
+    pub merge: Merge,
+    pub merge_downstream: bool,
+    pub last_fire: f32,
+    /// Pause when a signal arrives here.
+    pub breakpoint: bool,
 }
 impl Node {
     pub fn new(x: f32, y: f32) -> Self {
         Self {
             pos: vec2(x, y),
             kind: NodeKind::Default,
+            parent: None,
+            merge: Merge::Simultaneous,
+            merge_downstream: false,
+            last_fire: f32::NEG_INFINITY,
+            breakpoint: false,
         }
     }
+    pub fn rewind(&mut self) {
+        self.last_fire = f32::NEG_INFINITY;
+        match &mut self.kind {
+            NodeKind::Spawner {
+                next_spawn, phase, ..
+            } => *next_spawn = *phase * BAR_TIME,
+            NodeKind::Euclid(euclid) => {
+                euclid.next_step = euclid.phase * BAR_TIME;
+                euclid.step = 0;
+            }
+            NodeKind::Pattern(pattern) => pattern.last_step = None,
+            NodeKind::Script(script) => script.count = 0,
+            NodeKind::Trigger(trigger) => trigger.pending.clear(),
+            _ => {}
+        }
+    }
+    /// Returns false if an arrival at `time` should be merged into the previous one.
+    pub fn try_fire(&mut self, time: f32) -> bool {
+        let window = match self.merge {
+            Merge::Off => 0.0,
+            Merge::Simultaneous => SIMULTANEOUS_EPSILON,
+            Merge::Window(bars) => (bars * BAR_TIME).max(SIMULTANEOUS_EPSILON),
+        };
+        if (time - self.last_fire).abs() < window {
+            return false;
+        }
+        self.last_fire = time;
+        true
+    }
 }
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum NodeKind {
     Default,
-    Spawner { bar_delay: f32, next_spawn: f32 },
+    Spawner {
+        bar_delay: f32,
+        next_spawn: f32,
+        phase: f32,
+        payload: Payload,
+    },
     Sample(usize),
+    Transform(Transform),
+    Euclid(Euclid),
+    Pattern(Pattern),
+    Macro {
+        inlets: Vec<NodeId>,
+        outlets: Vec<NodeId>,
+    },
+    Inlet(usize),
+    Outlet(usize),
+    Script(Script),
+    Trigger(Trigger),
+    /// Passes signals while `open`, blocks them otherwise.
+    Switch {
+        open: bool,
+        key: Option<char>,
+    },
+}
+#[derive(Clone, PartialEq)]
+pub struct Euclid {
+    pub pulses: u32,
+    pub steps: u32,
+    pub rotation: u32,
+    pub cycle_bars: f32,
+    pub phase: f32,
+    pub next_step: f32,
+    pub step: u32,
+    pub payload: Payload,
+}
+impl Euclid {
+    pub fn new(pulses: u32, steps: u32) -> Self {
+        Self {
+            pulses,
+            steps,
+            rotation: 0,
+            cycle_bars: 1.0,
+            phase: 0.0,
+            next_step: 0.0,
+            step: 0,
+            payload: Payload::default(),
+        }
+    }
+    /// Whether step `i` of E(pulses, steps) is a pulse, spreading pulses as evenly as possible.
+    pub fn is_on(&self, i: u32) -> bool {
+        let n = self.steps.max(1);
+        let k = self.pulses.min(n);
+        ((i + self.rotation) % n) * k % n < k
+    }
+    pub fn step_time(&self) -> f32 {
+        self.cycle_bars.max(MIN_CYCLE_BARS) * BAR_TIME / self.steps.max(1) as f32
+    }
+    /// Advances past `time`, collecting the pulses on the way.
+    pub fn poll(&mut self, time: f32, out: &mut Vec<(f32, Payload)>) {
+        while self.next_step <= time {
+            if self.is_on(self.step) {
+                out.push((self.next_step, self.payload));
+            }
+            self.step = (self.step + 1) % self.steps.max(1);
+            self.next_step += self.step_time();
+        }
+    }
+}
+/// First time at or after `time` that lies on the `quantum` bar grid shifted by `phase` bars.
+pub fn next_launch(time: f32, quantum: f32, phase: f32) -> f32 {
+    let q = quantum.max(1.0 / 64.0) * BAR_TIME;
+    let p = phase * BAR_TIME;
+    ((time - p) / q).ceil() * q + p
+}
+impl NodeKind {
+    pub fn spawner(bar_delay: f32) -> Self {
+        Self::Spawner {
+            bar_delay,
+            next_spawn: 0.0,
+            phase: 0.0,
+            payload: Payload::default(),
+        }
+    }
+    /// Schedules a spawner to start on the next launch quantum.
+    pub fn launch(&mut self, time: f32, quantum: f32) {
+        match self {
+            Self::Spawner {
+                next_spawn, phase, ..
+            } => *next_spawn = next_launch(time, quantum, *phase),
+            Self::Euclid(euclid) => {
+                euclid.next_step = next_launch(time, quantum, euclid.phase);
+                euclid.step = 0;
+            }
+            Self::Pattern(pattern) => pattern.last_step = None,
+            _ => {}
+        }
+    }
+    /// The performance key this node listens to.
+    pub fn key(&self) -> Option<char> {
+        match self {
+            Self::Trigger(trigger) => Some(trigger.key),
+            Self::Switch { key, .. } => *key,
+            _ => None,
+        }
+    }
+    /// Reacts to a press of its key or a click in perform mode.
+    pub fn press(&mut self, time: f32) {
+        match self {
+            Self::Trigger(trigger) => trigger.press(time),
+            Self::Switch { open, .. } => *open = !*open,
+            _ => {}
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq)]
+pub struct Payload {
+    pub velocity: f32,
+    pub transpose: i32,
+    pub tag: Option<char>,
+}
+impl Default for Payload {
+    fn default() -> Self {
+        Self {
+            velocity: 1.0,
+            transpose: 0,
+            tag: None,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum Transform {
+    Velocity(f32),
+    Transpose(i32),
+    Tag(Option<char>),
+}
+impl Transform {
+    pub fn apply(&self, payload: &mut Payload) {
+        match *self {
+            Self::Velocity(factor) => payload.velocity *= factor,
+            Self::Transpose(semitones) => payload.transpose += semitones,
+            Self::Tag(tag) => payload.tag = tag,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum Merge {
+    Off,
+    Simultaneous,
+    Window(f32),
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum Step {
+    Off,
+    On,
+    Accent,
+}
+impl Step {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::On,
+            Self::On => Self::Accent,
+            Self::Accent => Self::Off,
+        }
+    }
+}
+#[derive(Clone, PartialEq)]
+pub struct Pattern {
+    pub steps: Vec<Step>,
+    pub cycle_bars: f32,
+    pub phase: f32,
+    pub last_step: Option<i64>,
+    pub payload: Payload,
+}
+impl Pattern {
+    pub fn new(len: usize) -> Self {
+        let mut steps = vec![Step::Off; len];
+        for (i, step) in steps.iter_mut().enumerate().step_by(4) {
+            *step = if i == 0 { Step::Accent } else { Step::On };
+        }
+        Self {
+            steps,
+            cycle_bars: 1.0,
+            phase: 0.0,
+            last_step: None,
+            payload: Payload::default(),
+        }
+    }
+    pub fn step_time(&self) -> f32 {
+        self.cycle_bars.max(MIN_CYCLE_BARS) * BAR_TIME / self.steps.len().max(1) as f32
+    }
+    /// Steps are counted from time zero, so patterns stay locked to the bar grid.
+    pub fn poll(&mut self, time: f32, out: &mut Vec<(f32, Payload)>) {
+        if self.steps.is_empty() {
+            return;
+        }
+        let offset = self.phase * BAR_TIME;
+        let time = time - offset;
+        let step_time = self.step_time();
+        let current = (time / step_time).floor() as i64;
+        let first = self
+            .last_step
+            .map_or_else(|| (time / step_time).ceil() as i64, |s| s + 1);
+        for s in first..=current {
+            let mut payload = self.payload;
+            match self.steps[s.rem_euclid(self.steps.len() as i64) as usize] {
+                Step::Off => continue,
+                Step::On => payload.velocity *= UNACCENTED,
+                Step::Accent => {}
+            }
+            out.push((s as f32 * step_time + offset, payload));
+        }
+        self.last_step = Some(current.max(first - 1));
+    }
+}
+#[derive(Clone, PartialEq)]
+pub struct Trigger {
+    pub key: char,
+    /// Wait for the next beat instead of firing right away.
+    pub quantize: bool,
+    pub pending: Vec<f32>,
+    pub payload: Payload,
+}
+impl Trigger {
+    pub fn new(key: char) -> Self {
+        Self {
+            key,
+            quantize: true,
+            pending: vec![],
+            payload: Payload::default(),
+        }
+    }
+    pub fn press(&mut self, time: f32) {
+        let beat = BAR_TIME / BEATS_PER_BAR as f32;
+        let at = if self.quantize {
+            (time / beat).ceil() * beat
+        } else {
+            time
+        };
+        self.pending.push(at);
+    }
+    pub fn poll(&mut self, time: f32, out: &mut Vec<(f32, Payload)>) {
+        let payload = self.payload;
+        self.pending.retain(|&at| {
+            if at <= time {
+                out.push((at, payload));
+            }
+            at > time
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn euclid_spreads_pulses() {
+        let euclid = Euclid::new(3, 8);
+        let steps: String = (0..8)
+            .map(|i| if euclid.is_on(i) { 'x' } else { '.' })
+            .collect();
+        assert_eq!(steps, "x..x..x.");
+    }
+
+    #[test]
+    fn euclid_with_an_empty_cycle_still_returns() {
+        let mut euclid = Euclid::new(3, 8);
+        let mut out = vec![];
+        for cycle_bars in [0.0, -1.0] {
+            euclid.cycle_bars = cycle_bars;
+            euclid.poll(BAR_TIME, &mut out);
+        }
+        assert!(euclid.next_step > BAR_TIME);
+    }
+
+    #[test]
+    fn pattern_steps_follow_the_grid() {
+        let mut pattern = Pattern::new(8);
+        let mut out = vec![];
+        pattern.poll(0.0, &mut out);
+        pattern.poll(0.99 * BAR_TIME, &mut out);
+        let times: Vec<(f32, f32)> = out.iter().map(|&(t, p)| (t, p.velocity)).collect();
+        assert_eq!(times, [(0.0, 1.0), (0.5 * BAR_TIME, UNACCENTED)]);
+    }
+
+    #[test]
+    fn pattern_with_an_empty_cycle_still_returns() {
+        let mut pattern = Pattern::new(8);
+        pattern.cycle_bars = 0.0;
+        let mut out = vec![];
+        pattern.poll(BAR_TIME, &mut out);
+        assert!(out.len() <= 64 * 2 + 1);
+    }
 }
@@ -0,0 +1,185 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Write as _;
+
+use anyhow::{Context, Result};
+use slotmap::SecondaryMap;
+
+use crate::analysis::{flow_graph, Flow};
+use crate::edge::EdgeKind;
+use crate::node::NodeKind;
+use crate::sim::Simulation;
+use crate::{NodeId, BAR_TIME, TICK_TIME};
+
+const LOOP_FILE: &str = "loop.txt";
+
+pub struct Period {
+    /// Ticks after which the whole patch repeats, `None` if nothing
+    /// repeats exactly.
+    pub ticks: Option<u64>,
+    /// Random edges or scripts are in play, so repeats may not be exact.
+    pub approximate: bool,
+}
+impl Period {
+    pub fn bars(&self) -> Option<f32> {
+        self.ticks.map(|ticks| ticks as f32 * TICK_TIME / BAR_TIME)
+    }
+}
+
+fn to_ticks(time: f32) -> u64 {
+    (time / TICK_TIME).round().max(0.0) as u64
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// `None` when it doesn't fit, nearly coprime laps get there quickly.
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    if a == 0 || b == 0 {
+        return Some(a.max(b));
+    }
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+impl Simulation {
+    /// LCM of every spawner interval, rhythm cycle and feedback lap, in ticks.
+    /// A lap is measured as the loop or wrap edge plus the shortest way back
+    /// to its start. There is no exact period when the LCM overflows.
+    pub fn period(&self) -> Period {
+        let mut ticks = Some(0);
+        let mut approximate = false;
+        for node in self.nodes.values() {
+            let cycle = match &node.kind {
+                NodeKind::Spawner { bar_delay, .. } => *bar_delay,
+                NodeKind::Euclid(euclid) => euclid.cycle_bars,
+                NodeKind::Pattern(pattern) => pattern.cycle_bars,
+                NodeKind::Script(_) => {
+                    approximate = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            ticks = ticks.and_then(|t| lcm(t, to_ticks(cycle * BAR_TIME)));
+        }
+        let flow = flow_graph(self);
+        for (id, edge) in &self.edges {
+            if !edge.enabled {
+                continue;
+            }
+            approximate |= edge.probability < 1.0;
+            if matches!(edge.kind, EdgeKind::Forward) {
+                continue;
+            }
+            let Some(duration) = edge.duration(&self.nodes) else {
+                continue;
+            };
+            let Some((u, v)) = flow
+                .iter()
+                .find_map(|(u, out)| out.iter().find(|&&(_, e)| e == id).map(|&(v, _)| (u, v)))
+            else {
+                continue;
+            };
+            if let Some(back) = self.shortest_ticks(&flow, v, u) {
+                let lap = to_ticks(duration + edge.delay * BAR_TIME) + back;
+                ticks = ticks.and_then(|t| lcm(t, lap));
+            }
+        }
+        Period {
+            ticks: ticks.filter(|&t| t > 0),
+            approximate,
+        }
+    }
+    /// Writes the triggers between `start` and `start + bars` bars to the
+    /// loop file, timed from the start of the region.
+    pub fn export_loop(&self, start: f32, bars: f32) -> Result<()> {
+        let (start, end) = (start * BAR_TIME, (start + bars) * BAR_TIME);
+        let mut text = format!("length {}\n", end - start);
+        for (time, sample) in self.predict_from_top(end / BAR_TIME) {
+            if time >= start && time < end {
+                let _ = writeln!(text, "{} {sample}", time - start);
+            }
+        }
+        std::fs::write(LOOP_FILE, text).with_context(|| format!("writing {LOOP_FILE}"))
+    }
+    /// Dijkstra over the flow graph, with edge delays.
+    fn shortest_ticks(&self, flow: &Flow, from: NodeId, to: NodeId) -> Option<u64> {
+        let mut best: SecondaryMap<NodeId, u64> = SecondaryMap::new();
+        let mut queue = BinaryHeap::from([Reverse((0, from))]);
+        while let Some(Reverse((ticks, u))) = queue.pop() {
+            if u == to {
+                return Some(ticks);
+            }
+            if best.get(u).is_some_and(|&b| b <= ticks) {
+                continue;
+            }
+            best.insert(u, ticks);
+            for &(v, e) in &flow[u] {
+                let edge = &self.edges[e];
+                let duration = edge.duration(&self.nodes).unwrap_or(0.0);
+                queue.push(Reverse((
+                    ticks + to_ticks(duration + edge.delay * BAR_TIME),
+                    v,
+                )));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::Edge;
+    use crate::node::{Euclid, Node};
+    use crate::PX_PER_BAR;
+
+    #[test]
+    fn lcm_overflow_is_none() {
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(0, 5), Some(5));
+        assert_eq!(lcm(u64::MAX - 1, u64::MAX - 2), None);
+    }
+
+    #[test]
+    fn rhythms_and_laps() {
+        let mut sim = Simulation::new();
+        let mut spawner = Node::new(0.0, 0.0);
+        spawner.kind = NodeKind::spawner(1.0);
+        let mut euclid = Euclid::new(3, 8);
+        euclid.cycle_bars = 0.75;
+        let mut node = Node::new(0.0, 50.0);
+        node.kind = NodeKind::Euclid(euclid);
+        let (s, _) = (sim.add_node(spawner), sim.add_node(node));
+        assert_eq!(sim.period().bars(), Some(3.0));
+        // a lap of one bar out and 1.25 bars back
+        let v = sim.add_node(Node::new(PX_PER_BAR, 0.0));
+        sim.add_edge(Edge::new(s, v));
+        let back = sim.add_edge(Edge::new(v, s));
+        sim.edges[back].kind = EdgeKind::Loop { bars: 1.25 };
+        let period = sim.period();
+        assert_eq!(period.bars(), Some(9.0));
+        assert!(!period.approximate);
+    }
+
+    #[test]
+    fn many_odd_laps_have_no_exact_period() {
+        let mut sim = Simulation::new();
+        let mut spawner = Node::new(0.0, 0.0);
+        spawner.kind = NodeKind::spawner(1.0);
+        let s = sim.add_node(spawner);
+        for i in 0..7 {
+            let v = sim.add_node(Node::new(PX_PER_BAR, 10.0 * i as f32));
+            sim.add_edge(Edge::new(s, v));
+            let back = sim.add_edge(Edge::new(v, s));
+            sim.edges[back].kind = EdgeKind::Loop {
+                bars: 3.99 + 0.037 * i as f32,
+            };
+        }
+        assert_eq!(sim.period().ticks, None);
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt::Write as _;
+
+use anyhow::{Context, Result};
+
+use crate::sim::Simulation;
+use crate::BAR_TIME;
+
+const SCHEDULE_FILE: &str = "schedule.txt";
+
+/// Sample triggers as `(time, sample)`.
+pub type Schedule = Vec<(f32, usize)>;
+
+impl Simulation {
+    /// Plays a copy of the patch from bar 1 for `bars` bars, with the
+    /// current seed, and lists the samples it triggers. Starting from the
+    /// top keeps schedules comparable while the clock runs.
+    pub fn predict_from_top(&self, bars: f32) -> Schedule {
+        let mut sim = self.clone();
+        sim.seed_locked = true;
+        sim.recorder.recording = false;
+        sim.recorder.replay = None;
+        for node in sim.nodes.values_mut() {
+            node.breakpoint = false;
+        }
+        sim.rewind();
+        sim.paused = false;
+        let mut schedule = vec![];
+        while sim.time < bars * BAR_TIME && !sim.paused {
+            for event in sim.advance() {
+                if let Some(sample) = event.sample {
+                    schedule.push((event.time, sample));
+                }
+            }
+        }
+        schedule
+    }
+}
+
+/// The schedule overlay and a pinned schedule to compare against.
+#[derive(Default)]
+pub struct Preview {
+    pub show: bool,
+    pub bars: u32,
+    pub schedule: Schedule,
+    pub pinned: Option<Schedule>,
+    /// Length the schedule was made for, `None` while hidden.
+    pub made_for: Option<u32>,
+    /// Export region in bars. Starting one period in skips the first pass,
+    /// where loops are still filling up.
+    pub loop_start: f32,
+    pub loop_bars: f32,
+    /// Outcome of the last save or export.
+    pub status: String,
+}
+impl Preview {
+    /// Writes the schedule to the schedule file, one `time sample` per line.
+    pub fn save(&self) -> Result<()> {
+        let mut text = String::new();
+        for (time, sample) in &self.schedule {
+            let _ = writeln!(text, "{time} {sample}");
+        }
+        std::fs::write(SCHEDULE_FILE, text).with_context(|| format!("writing {SCHEDULE_FILE}"))
+    }
+}
+
+/// Number of triggers in one schedule without a match in the other.
+pub fn difference(a: &Schedule, b: &Schedule) -> usize {
+    const EPSILON: f32 = 1e-3;
+    let unmatched = |a: &Schedule, b: &Schedule| {
+        a.iter()
+            .filter(|&&(time, sample)| {
+                !b.iter()
+                    .any(|&(t, s)| s == sample && (t - time).abs() < EPSILON)
+            })
+            .count()
+    };
+    unmatched(a, b) + unmatched(b, a)
+}
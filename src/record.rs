@@ -0,0 +1,322 @@
+use std::fmt::Write as _;
+
+use anyhow::{anyhow, bail, Context, Result};
+use slotmap::{Key as _, KeyData, SecondaryMap};
+
+use crate::node::NodeKind;
+use crate::sim::Simulation;
+use crate::NodeId;
+
+const TAKE_FILE: &str = "performance.txt";
+
+/// A live action that the recorder captures and can replay. Takes record
+/// a performance over a fixed patch: edits to nodes and edges, inspector
+/// changes and breakpoints are not captured, so a take replays faithfully
+/// only on the patch it was recorded with. Stop and rewind end a take.
+#[derive(Clone, Copy)]
+pub enum Action {
+    /// A performance key, pressing every trigger and switch bound to it.
+    Key(char),
+    /// A click on a node in perform mode.
+    Press(NodeId),
+    QueueScene(usize),
+    /// Switch to the queued scene right away.
+    SceneNow,
+    ToggleMute(NodeId),
+    KillSignals,
+    Pause,
+}
+
+#[derive(Clone, Default)]
+pub struct Recorder {
+    pub recording: bool,
+    pub take: Vec<(f32, Action)>,
+    /// Index of the next action to replay while a replay is running.
+    pub replay: Option<usize>,
+    /// Outcome of the last save or load.
+    pub status: String,
+    /// Seed the take was recorded with.
+    pub seed: u64,
+    pub setup: Setup,
+}
+
+/// What the actions of a take change, as it was when the take started.
+#[derive(Clone, Default)]
+pub struct Setup {
+    /// Muted nodes of each scene.
+    pub mutes: Vec<SecondaryMap<NodeId, ()>>,
+    pub scene: usize,
+    pub switches: Vec<(NodeId, bool)>,
+}
+
+impl Simulation {
+    /// Applies a live action, recording it when a take is being recorded.
+    pub fn perform(&mut self, action: Action) {
+        if self.recorder.recording {
+            self.recorder.take.push((self.time, action));
+        }
+        self.apply(action, self.time);
+    }
+    fn apply(&mut self, action: Action, time: f32) {
+        self.edited = true;
+        match action {
+            Action::Key(key) => {
+                for node in self.nodes.values_mut() {
+                    if node.kind.key() == Some(key) {
+                        node.kind.press(time);
+                    }
+                }
+            }
+            Action::Press(id) => {
+                if let Some(node) = self.nodes.get_mut(id) {
+                    node.kind.press(time);
+                }
+            }
+            Action::QueueScene(scene) => self.queue_scene(scene.min(self.scenes.len() - 1), time),
+            Action::SceneNow => {
+                if let Some((scene, _)) = self.queued_scene.take() {
+                    self.scene = scene;
+                }
+            }
+            Action::ToggleMute(id) => {
+                if self.nodes.contains_key(id) {
+                    self.toggle_muted(id);
+                }
+            }
+            Action::KillSignals => self.kill_signals(),
+            Action::Pause => self.paused = !self.paused,
+        }
+    }
+    /// Starts a new take from the top.
+    pub fn record(&mut self) {
+        self.rewind();
+        self.recorder.seed = self.seed;
+        self.recorder.setup = Setup {
+            mutes: self.scenes.iter().map(|s| s.muted.clone()).collect(),
+            scene: self.scene,
+            switches: self
+                .nodes
+                .iter()
+                .filter_map(|(id, node)| match node.kind {
+                    NodeKind::Switch { open, .. } => Some((id, open)),
+                    _ => None,
+                })
+                .collect(),
+        };
+        self.recorder.take.clear();
+        self.recorder.recording = true;
+        self.recorder.replay = None;
+        self.paused = false;
+    }
+    pub fn replay(&mut self) {
+        self.rewind();
+        self.reseed(self.recorder.seed);
+        let setup = self.recorder.setup.clone();
+        for (scene, muted) in self.scenes.iter_mut().zip(setup.mutes) {
+            scene.muted = muted;
+        }
+        if setup.scene < self.scenes.len() {
+            self.scene = setup.scene;
+        }
+        for (id, was_open) in setup.switches {
+            if let Some(NodeKind::Switch { open, .. }) = self.nodes.get_mut(id).map(|n| &mut n.kind)
+            {
+                *open = was_open;
+            }
+        }
+        self.recorder.recording = false;
+        self.recorder.replay = Some(0);
+        self.paused = false;
+    }
+    /// Applies the recorded actions that are due, at the time they were taken.
+    /// Live actions come in after their tick was processed, so they are
+    /// replayed at the start of the next one.
+    pub fn update_replay(&mut self) {
+        let Some(mut next) = self.recorder.replay else {
+            return;
+        };
+        while let Some(&(time, action)) = self.recorder.take.get(next) {
+            if time >= self.time {
+                break;
+            }
+            self.apply(action, time);
+            next += 1;
+        }
+        self.recorder.replay = (next < self.recorder.take.len()).then_some(next);
+    }
+    pub fn save_take(&self) -> Result<()> {
+        std::fs::write(TAKE_FILE, self.take_text()).with_context(|| format!("writing {TAKE_FILE}"))
+    }
+    pub fn load_take(&mut self) -> Result<()> {
+        let text =
+            std::fs::read_to_string(TAKE_FILE).with_context(|| format!("reading {TAKE_FILE}"))?;
+        self.parse_take(&text)
+    }
+    /// The seed and setup as header lines, then one action per line.
+    fn take_text(&self) -> String {
+        let id = |id: NodeId| id.data().as_ffi();
+        let setup = &self.recorder.setup;
+        let mut text = format!("seed {}\nstart-scene {}\n", self.recorder.seed, setup.scene);
+        for (scene, muted) in setup.mutes.iter().enumerate() {
+            let _ = write!(text, "muted {scene}");
+            for node in muted.keys() {
+                let _ = write!(text, " {}", id(node));
+            }
+            text.push('\n');
+        }
+        for &(node, open) in &setup.switches {
+            let _ = writeln!(text, "switch {} {}", id(node), open as u8);
+        }
+        for &(time, action) in &self.recorder.take {
+            let _ = match action {
+                Action::Key(key) => writeln!(text, "{time} key {key}"),
+                Action::Press(node) => writeln!(text, "{time} press {}", id(node)),
+                Action::QueueScene(scene) => writeln!(text, "{time} scene {scene}"),
+                Action::SceneNow => writeln!(text, "{time} scene-now"),
+                Action::ToggleMute(node) => writeln!(text, "{time} mute {}", id(node)),
+                Action::KillSignals => writeln!(text, "{time} kill"),
+                Action::Pause => writeln!(text, "{time} pause"),
+            };
+        }
+        text
+    }
+    fn parse_take(&mut self, text: &str) -> Result<()> {
+        let mut take = vec![];
+        let mut seed = self.seed;
+        let mut setup = Setup::default();
+        for (i, line) in text.lines().enumerate() {
+            let context = || format!("line {}", i + 1);
+            if let Some(value) = line.strip_prefix("seed ") {
+                seed = value.trim().parse().with_context(context)?;
+            } else if let Some(value) = line.strip_prefix("start-scene ") {
+                setup.scene = value.trim().parse().with_context(context)?;
+            } else if let Some(value) = line.strip_prefix("muted ") {
+                // the scene index comes first, scenes are listed in order
+                let mut muted = SecondaryMap::new();
+                for word in value.split_whitespace().skip(1) {
+                    muted.insert(parse_node(word).with_context(context)?, ());
+                }
+                setup.mutes.push(muted);
+            } else if let Some(value) = line.strip_prefix("switch ") {
+                let (node, open) = value
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("line is too short"))
+                    .with_context(context)?;
+                let node = parse_node(node).with_context(context)?;
+                setup.switches.push((node, open.trim() == "1"));
+            } else {
+                take.push(parse_action(line).with_context(context)?);
+            }
+        }
+        self.recorder.take = take;
+        self.recorder.seed = seed;
+        self.recorder.setup = setup;
+        self.recorder.recording = false;
+        self.recorder.replay = None;
+        Ok(())
+    }
+}
+
+fn parse_node(word: &str) -> Result<NodeId> {
+    Ok(KeyData::from_ffi(word.parse()?).into())
+}
+
+fn parse_action(line: &str) -> Result<(f32, Action)> {
+    let mut words = line.split_whitespace();
+    let mut next = || words.next().ok_or_else(|| anyhow!("line is too short"));
+    let time = next()?.parse()?;
+    let action = match next()? {
+        "key" => Action::Key(next()?.chars().next().unwrap_or(' ')),
+        "press" => Action::Press(parse_node(next()?)?),
+        "scene" => Action::QueueScene(next()?.parse()?),
+        "scene-now" => Action::SceneNow,
+        "mute" => Action::ToggleMute(parse_node(next()?)?),
+        "kill" => Action::KillSignals,
+        "pause" => Action::Pause,
+        other => bail!("unknown action `{other}`"),
+    };
+    Ok((time, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::Edge;
+    use crate::node::{Node, Trigger};
+    use crate::{BAR_TIME, PX_PER_BAR, TICKS_PER_BEAT, TICK_TIME};
+
+    /// Plays `ticks` ticks, performing the actions due after each tick like
+    /// the input handler does, and lists what fired.
+    fn play(sim: &mut Simulation, ticks: u64, actions: &[(u64, Action)]) -> Vec<(f32, NodeId)> {
+        let mut fired = vec![];
+        for _ in 0..ticks {
+            fired.extend(sim.advance().iter().map(|e| (e.time, e.node)));
+            for &(tick, action) in actions {
+                if tick == sim.tick {
+                    sim.perform(action);
+                }
+            }
+        }
+        fired
+    }
+
+    #[test]
+    fn replay_matches_the_live_take() {
+        let mut sim = Simulation::new();
+        let mut spawner = Node::new(0.0, 0.0);
+        spawner.kind = NodeKind::spawner(1.0);
+        let mut trigger = Node::new(0.0, 50.0);
+        let mut key = Trigger::new('Q');
+        key.quantize = false;
+        trigger.kind = NodeKind::Trigger(key);
+        let sample = sim.add_node(Node::new(PX_PER_BAR, 0.0));
+        for node in [spawner, trigger] {
+            let id = sim.add_node(node);
+            sim.add_edge(Edge::new(id, sample));
+        }
+        let bar = (BAR_TIME / TICK_TIME).round() as u64;
+        let actions = [
+            (bar, Action::KillSignals),
+            (bar + TICKS_PER_BEAT as u64 + 1, Action::Key('Q')),
+            (3 * bar, Action::ToggleMute(sample)),
+        ];
+        sim.record();
+        let live = play(&mut sim, 4 * bar, &actions);
+        sim.recorder.recording = false;
+        assert_eq!(live.len(), 3);
+        sim.replay();
+        assert_eq!(live, play(&mut sim, 4 * bar, &[]));
+    }
+
+    #[test]
+    fn saved_takes_replay_from_their_setup() {
+        let mut sim = Simulation::new();
+        let mut spawner = Node::new(0.0, 0.0);
+        spawner.kind = NodeKind::spawner(0.5);
+        let s = sim.add_node(spawner);
+        let mut switch = Node::new(0.5 * PX_PER_BAR, 0.0);
+        switch.kind = NodeKind::Switch {
+            open: true,
+            key: None,
+        };
+        let switch = sim.add_node(switch);
+        let a = sim.add_node(Node::new(PX_PER_BAR, 0.0));
+        let b = sim.add_node(Node::new(PX_PER_BAR, 50.0));
+        sim.add_edge(Edge::new(s, switch));
+        sim.add_edge(Edge::new(switch, a));
+        sim.add_edge(Edge::new(s, b));
+        let bar = (BAR_TIME / TICK_TIME).round() as u64;
+        let actions = [
+            (bar, Action::ToggleMute(b)),
+            (2 * bar, Action::Press(switch)),
+        ];
+        sim.record();
+        let live = play(&mut sim, 4 * bar, &actions);
+        sim.recorder.recording = false;
+        let text = sim.take_text();
+        sim.recorder = Recorder::default();
+        sim.parse_take(&text).unwrap();
+        sim.replay();
+        assert_eq!(live, play(&mut sim, 4 * bar, &[]));
+    }
+}
@@ -1,103 +1,130 @@
 use std::f32::consts::PI;
 
 use crate::*;
+use predict::{Preview, Schedule};
 use translation::Translatable;
 
 const GHOST_COLOR: Color = Color {
     a: 0.5,
     ..LIGHTGRAY
 };
+const LOOP_COLOR: Color = SKYBLUE;
 pub fn draw_mode_overlays(state: &State) {
-    if matches!(state.mode, Mode::AddEdge { .. } | Mode::UpdNode { .. }) {
+    if matches!(
+        state.mode,
+        Mode::AddEdge { .. } | Mode::UpdNode { .. } | Mode::Perform
+    ) {
         if let Some(id) = state.hovered_node {
-            let t = state.nodes[id].translate(state.camera_pos);
-            let sz = 15.0;
-            draw_rectangle_lines_ex(
-                t.pos.x,
-                t.pos.y,
-                sz,
-                sz,
-                2.0,
-                DrawRectangleParams {
-                    offset: vec2(0.5, 0.5),
-                    color: GHOST_COLOR,
-                    rotation: PI / 4.0,
-                },
-            );
-        }
-    }
-    if matches!(state.mode, Mode::AddEdge { .. }) {
-        if state.hovered_node.is_none() {
-            draw_node(
-                state.mouse_pos.translate(state.camera_pos).pos,
+            draw_marker(
+                state.sim.nodes[id].pos.translate(state.camera_pos).pos,
                 GHOST_COLOR,
-                None,
-                state,
             );
         }
     }
+    for &id in &state.group {
+        draw_marker(
+            state.sim.nodes[id].pos.translate(state.camera_pos).pos,
+            LIME,
+        );
+    }
+    if let Some(sel) = state.selection {
+        draw_marker(
+            sel.get_center(state).translate(state.camera_pos).pos,
+            YELLOW,
+        );
+    }
+    if matches!(state.mode, Mode::AddEdge { .. }) && state.hovered_node.is_none() {
+        draw_node(
+            state.mouse_pos.translate(state.camera_pos).pos,
+            GHOST_COLOR,
+            None,
+            state,
+        );
+    }
     match &state.mode {
         Mode::Delete(Some(sel)) => {
-            let pos = sel.get_center(state);
-            let t = pos.translate(state.camera_pos);
-            let sz = 15.0;
-            draw_rectangle_lines_ex(
-                t.pos.x,
-                t.pos.y,
-                sz,
-                sz,
-                2.0,
-                DrawRectangleParams {
-                    offset: vec2(0.5, 0.5),
-                    color: RED,
-                    rotation: PI / 4.0,
-                },
-            );
+            draw_marker(sel.get_center(state).translate(state.camera_pos).pos, RED);
         }
-        Mode::AddEdge { first } => {
-            if let Some(first_id) = *first {
-                let a = state.nodes[first_id].pos.translate(state.camera_pos);
-                let b = state
-                    .hovered_node
-                    .map(|id| state.nodes[id].pos)
-                    .unwrap_or(state.mouse_pos);
+        Mode::AddEdge {
+            first: Some(first_id),
+        } => {
+            let a = state.sim.nodes[*first_id].pos.translate(state.camera_pos);
+            let b = state
+                .hovered_node
+                .map(|id| state.sim.nodes[id].pos)
+                .unwrap_or(state.mouse_pos);
 
-                draw_arrow(a.pos, b.translate(state.camera_pos).pos, GHOST_COLOR);
-            }
+            draw_arrow(a.pos, b.translate(state.camera_pos).pos, GHOST_COLOR);
         }
         Mode::UpdNode { kind } => {
             let pos = state
                 .hovered_node
-                .map(|id| state.nodes[id].pos)
+                .map(|id| state.sim.nodes[id].pos)
                 .unwrap_or(state.mouse_pos)
                 .translate(state.camera_pos)
                 .pos;
             draw_node(pos, GHOST_COLOR, Some(kind), state);
         }
+        Mode::Perform => {
+            let text = "PERFORM (Tab to edit)";
+            let dims = measure_text(text, None, 22, 1.0);
+            draw_text(
+                text,
+                (screen_width() - dims.width) / 2.0,
+                60.0,
+                22.0,
+                ORANGE,
+            );
+        }
 
         _ => {}
     }
 }
+pub fn draw_marker(pos: Vec2, color: Color) {
+    let sz = 15.0;
+    draw_rectangle_lines_ex(
+        pos.x,
+        pos.y,
+        sz,
+        sz,
+        2.0,
+        DrawRectangleParams {
+            offset: vec2(0.5, 0.5),
+            color,
+            rotation: PI / 4.0,
+        },
+    );
+}
+pub fn draw_centered_text(text: &str, pos: Vec2, size: u16, color: Color) {
+    let dims = measure_text(text, None, size, 1.0);
+    draw_text(
+        text,
+        pos.x - dims.width / 2.0,
+        pos.y + dims.height / 2.0,
+        size as f32,
+        color,
+    );
+}
 pub fn draw_node(pos: Vec2, color: Color, kind: Option<&NodeKind>, state: &State) {
     draw_circle_lines(pos.x, pos.y, NODE_RADIUS, 1.5, color);
     if let Some(kind) = kind {
         match kind {
-            NodeKind::Sample(idx) => {
-                let text = idx.to_string();
-                let dims = measure_text(&text, None, 22, 1.0);
-                draw_text(
-                    &text,
-                    pos.x - dims.width / 2.0,
-                    pos.y + dims.height / 2.0,
-                    22.0,
-                    color,
-                );
+            NodeKind::Sample(idx) => draw_centered_text(&idx.to_string(), pos, 22, color),
+            NodeKind::Transform(transform) => {
+                let text = match transform {
+                    Transform::Velocity(factor) => format!("x{factor:.1}"),
+                    Transform::Transpose(semitones) => format!("{semitones:+}"),
+                    Transform::Tag(Some(tag)) => format!("#{tag}"),
+                    Transform::Tag(None) => "#-".to_string(),
+                };
+                draw_centered_text(&text, pos, 14, color);
             }
             NodeKind::Spawner {
                 bar_delay,
                 next_spawn,
+                ..
             } => {
-                let t = (next_spawn - state.time) / (bar_delay * BAR_TIME);
+                let t = (next_spawn - state.sim.time) / (bar_delay * BAR_TIME);
                 draw_arc(
                     pos.x,
                     pos.y,
@@ -109,10 +136,69 @@ pub fn draw_node(pos: Vec2, color: Color, kind: Option<&NodeKind>, state: &State
                     GREEN,
                 );
             }
+            NodeKind::Euclid(euclid) => {
+                let n = euclid.steps.max(1);
+                let steps: Vec<Step> = (0..n)
+                    .map(|i| if euclid.is_on(i) { Step::On } else { Step::Off })
+                    .collect();
+                draw_step_ring(pos, &steps, ((euclid.step + n - 1) % n) as usize);
+                let text = format!("{}/{}", euclid.pulses, euclid.steps);
+                draw_centered_text(&text, pos, 12, color);
+            }
+            NodeKind::Macro { inlets, outlets } => {
+                let r = NODE_RADIUS + 3.0;
+                draw_rectangle_lines(pos.x - r, pos.y - r, 2.0 * r, 2.0 * r, 1.5, color);
+                let text = format!("{}:{}", inlets.len(), outlets.len());
+                draw_centered_text(&text, pos, 14, color);
+            }
+            NodeKind::Inlet(port) => draw_centered_text(&format!("in{port}"), pos, 12, color),
+            NodeKind::Outlet(port) => draw_centered_text(&format!("out{port}"), pos, 12, color),
+            NodeKind::Pattern(pattern) => {
+                let n = pattern.steps.len().max(1) as i64;
+                let current = pattern.last_step.unwrap_or(-1).rem_euclid(n);
+                draw_step_ring(pos, &pattern.steps, current as usize);
+            }
+            NodeKind::Trigger(trigger) => {
+                let lit = !trigger.pending.is_empty();
+                draw_circle_lines(
+                    pos.x,
+                    pos.y,
+                    NODE_RADIUS + 3.0,
+                    2.0,
+                    if lit { YELLOW } else { color },
+                );
+                draw_centered_text(&trigger.key.to_string(), pos, 20, color);
+            }
+            NodeKind::Switch { open, key } => {
+                if *open {
+                    draw_circle(pos.x, pos.y, NODE_RADIUS - 3.0, Color { a: 0.5, ..GREEN });
+                }
+                let text = match key {
+                    Some(key) => format!("{}{key}", if *open { "|" } else { "/" }),
+                    None => (if *open { "|" } else { "/" }).to_string(),
+                };
+                draw_centered_text(&text, pos, 20, color);
+            }
+            NodeKind::Script(script) => {
+                let failed = script.program.is_err() || script.error.is_some();
+                draw_centered_text("?", pos, 20, if failed { RED } else { color });
+            }
             _ => {}
         }
     }
 }
+pub fn draw_step_ring(pos: Vec2, steps: &[Step], current: usize) {
+    for (i, step) in steps.iter().enumerate() {
+        let dir = Vec2::from_angle(2.0 * PI * i as f32 / steps.len() as f32 - PI / 2.0);
+        let p = pos + dir * (NODE_RADIUS + 5.0);
+        let c = if i == current { YELLOW } else { GREEN };
+        match step {
+            Step::Off => draw_circle_lines(p.x, p.y, 2.0, 1.0, c),
+            Step::On => draw_circle(p.x, p.y, 2.0, c),
+            Step::Accent => draw_circle(p.x, p.y, 3.0, c),
+        }
+    }
+}
 pub fn draw_arrow(u: Vec2, v: Vec2, color: Color) {
     let dir = (v - u).normalize();
     let norm = dir.perp();
@@ -124,46 +210,206 @@ pub fn draw_arrow(u: Vec2, v: Vec2, color: Color) {
     draw_line(b.x, b.y, lb.x, lb.y, 1.0, color);
     draw_line(b.x, b.y, rb.x, rb.y, 1.0, color);
 }
+pub fn draw_curve_arrow(points: &[Vec2], color: Color) {
+    let (Some(&u), Some(&v)) = (points.first(), points.last()) else {
+        return;
+    };
+    let points: Vec<Vec2> = points
+        .iter()
+        .copied()
+        .filter(|p| p.distance(u) > NODE_RADIUS && p.distance(v) > NODE_RADIUS)
+        .collect();
+    for w in points.windows(2) {
+        draw_line(w[0].x, w[0].y, w[1].x, w[1].y, 1.0, color);
+    }
+    if let [.., a, b] = points[..] {
+        let dir = (b - a).normalize();
+        let norm = dir.perp();
+        let lb = b - dir * 14.0 - norm * 5.0;
+        let rb = b - dir * 14.0 + norm * 5.0;
+        draw_line(b.x, b.y, lb.x, lb.y, 1.0, color);
+        draw_line(b.x, b.y, rb.x, rb.y, 1.0, color);
+    }
+}
+pub fn draw_edge(id: EdgeId, edge: &Edge, state: &State) {
+    let (u, v) = edge.nodes;
+    let mut color = if edge.is_loop() { LOOP_COLOR } else { WHITE };
+    if !edge.enabled {
+        color = DARKGRAY;
+    }
+    let mut text = vec![];
+    if !edge.label.is_empty() {
+        text.push(edge.label.clone());
+    }
+    match edge.kind {
+        EdgeKind::Forward => {
+            let u = state.sim.nodes[u].translate(state.camera_pos);
+            let v = state.sim.nodes[v].translate(state.camera_pos);
+            draw_arrow(u.pos, v.pos, color);
+        }
+        EdgeKind::Loop { bars } | EdgeKind::Wrap { bars } => {
+            let points: Vec<Vec2> = edge
+                .curve_points(&state.sim.nodes)
+                .into_iter()
+                .map(|p| p.translate(state.camera_pos).pos)
+                .collect();
+            draw_curve_arrow(&points, color);
+            let name = if matches!(edge.kind, EdgeKind::Loop { .. }) {
+                "loop"
+            } else {
+                "wrap"
+            };
+            text.push(format!("{name} {bars}"));
+        }
+    }
+    if edge.delay != 0.0 {
+        text.push(format!("+{}", edge.delay));
+    }
+    if edge.probability < 1.0 {
+        text.push(format!("{:.0}%", edge.probability * 100.0));
+    }
+    if !text.is_empty() {
+        let mid = edge
+            .point_at(&state.sim.nodes, 0.5)
+            .translate(state.camera_pos)
+            .pos;
+        draw_text(&text.join(" "), mid.x + 4.0, mid.y - 4.0, 16.0, color);
+    }
+    if let Some(warnings) = state.analysis.edges.get(id) {
+        let mid = edge
+            .point_at(&state.sim.nodes, 0.5)
+            .translate(state.camera_pos)
+            .pos;
+        draw_warnings(warnings, mid + vec2(4.0, 14.0));
+    }
+}
+
+pub fn draw_warnings(warnings: &[Warning], pos: Vec2) {
+    for (i, warning) in warnings.iter().enumerate() {
+        let color = match warning {
+            Warning::Cycle => LOOP_COLOR,
+            Warning::Growth => ORANGE,
+            Warning::Stalled => RED,
+            Warning::Unreachable => GRAY,
+        };
+        draw_text(
+            warning.describe(),
+            pos.x,
+            pos.y + 12.0 * i as f32,
+            14.0,
+            color,
+        );
+    }
+}
 
 pub fn draw(state: &State) {
-    let nodes = &state.nodes;
-    let edges = &state.edges;
+    let nodes = &state.sim.nodes;
+    let edges = &state.sim.edges;
+    // inside a macro the bar lines follow the macro's place on the timeline
+    let offset = state.view_offset().rem_euclid(PX_PER_BAR);
     for i in 0..=(screen_width() as usize).div_ceil(PX_PER_BAR as usize) {
         let p = vec2(
-            (i as f32) * PX_PER_BAR + (state.camera_pos.x / PX_PER_BAR).round() * PX_PER_BAR,
+            (i as f32) * PX_PER_BAR + (state.camera_pos.x / PX_PER_BAR).round() * PX_PER_BAR
+                - offset,
             0.0,
         )
         .translate(state.camera_pos)
         .pos;
         draw_line(p.x, 0.0, p.x, screen_height(), 1.0, DARKGRAY);
     }
-    for node in nodes.values() {
+    for (id, node) in nodes.iter().filter(|(_, node)| node.parent == state.view) {
         let node = node.translate(state.camera_pos);
-        draw_node(node.pos, WHITE, Some(&node.inner.kind), state);
+        let color = if state.sim.is_muted(id) {
+            DARKGRAY
+        } else {
+            WHITE
+        };
+        draw_node(node.pos, color, Some(&node.inner.kind), state);
+        if node.inner.breakpoint {
+            let p = node.pos + vec2(-NODE_RADIUS, -NODE_RADIUS);
+            draw_circle(p.x, p.y, 4.0, RED);
+        }
+        if state.sim.halted == Some(id) {
+            draw_circle_lines(node.pos.x, node.pos.y, NODE_RADIUS + 4.0, 2.0, YELLOW);
+        }
+        if let Some(warnings) = state.analysis.nodes.get(id) {
+            draw_warnings(warnings, node.pos + vec2(-NODE_RADIUS, NODE_RADIUS + 14.0));
+        }
     }
-    for &Edge { nodes: (u, v) } in edges.values() {
-        let u = nodes[u].translate(state.camera_pos);
-        let v = nodes[v].translate(state.camera_pos);
-        draw_arrow(u.pos, v.pos, WHITE);
+    for (id, edge) in edges.iter().filter(|(_, edge)| state.in_view(edge.nodes.0)) {
+        draw_edge(id, edge, state);
     }
-    for particle in &state.particles {
+    for particle in state.particles.iter().filter(|p| p.view == state.view) {
         let particle = particle.translate(state.camera_pos);
-        let t = 3.0 * (state.time - particle.inner.end_time);
+        let t = 3.0 * (state.sim.time - particle.inner.end_time);
         draw_circle(particle.pos.x, particle.pos.y, (t).min(1.0), PINK);
     }
     for &Signal {
         cur_edge,
         start_time,
-    } in &state.signals
+        ..
+    } in state.sim.signals.values()
     {
-        let Edge { nodes: (u, v) } = edges[cur_edge];
-        let mut dir = nodes[v].pos - nodes[u].pos;
-        dir = dir / dir.x;
-        let t = state.time - start_time;
-        let pos = nodes[u].pos + dir * (t / BAR_TIME) * PX_PER_BAR;
+        let edge = &edges[cur_edge];
+        if !state.in_view(edge.nodes.0) {
+            continue;
+        }
+        let t = match edge.duration(nodes) {
+            Some(duration) if duration > 0.0 => {
+                ((state.sim.time - start_time) / duration).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        };
+        let pos = edge.point_at(nodes, t);
         let t = pos.translate(state.camera_pos);
         draw_circle(t.pos.x, t.pos.y, 5.0, PINK);
     }
+    if state.sim.signal_warning() {
+        let text = format!(
+            "signal cap reached, {} dropped (K to kill all)",
+            state.sim.dropped_signals
+        );
+        let dims = measure_text(&text, None, 22, 1.0);
+        draw_text(&text, (screen_width() - dims.width) / 2.0, 90.0, 22.0, RED);
+    }
+    if state.preview.show {
+        draw_preview(&state.preview, state.sim.time);
+    }
     draw_mode_overlays(state);
 }
 
+/// One row per sample, pinned triggers in gray behind the current ones.
+pub fn draw_preview(preview: &Preview, time: f32) {
+    const ROWS: usize = 10;
+    const ROW_HEIGHT: f32 = 8.0;
+    let (x, y) = (10.0, 120.0);
+    let width = screen_width() - 280.0;
+    let span = preview.bars.max(1) as f32 * BAR_TIME;
+    let height = ROWS as f32 * ROW_HEIGHT;
+    draw_rectangle(x, y, width, height, Color { a: 0.6, ..BLACK });
+    for bar in 0..=preview.bars {
+        let bx = x + width * bar as f32 * BAR_TIME / span;
+        draw_line(bx, y, bx, y + height, 1.0, DARKGRAY);
+    }
+    let mark = |schedule: &Schedule, color: Color, grow: f32| {
+        for &(t, sample) in schedule {
+            let tx = x + width * t / span;
+            let ty = y + (sample % ROWS) as f32 * ROW_HEIGHT;
+            draw_rectangle(
+                tx - grow,
+                ty - grow,
+                3.0 + 2.0 * grow,
+                ROW_HEIGHT - 2.0 + 2.0 * grow,
+                color,
+            );
+        }
+    };
+    if let Some(pinned) = &preview.pinned {
+        mark(pinned, GRAY, 1.0);
+    }
+    mark(&preview.schedule, PINK, 0.0);
+    if time < span {
+        let px = x + width * time / span;
+        draw_line(px, y, px, y + height, 1.0, YELLOW);
+    }
+}
@@ -0,0 +1,30 @@
+/// Small xorshift generator. The simulation and the visuals each own one,
+/// so drawing never changes the musical outcome of a seed.
+#[derive(Clone)]
+pub struct Rng(u64);
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(0x9E37_79B9_7F4A_7C15)
+    }
+}
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift is stuck at zero
+        Self(seed.max(1))
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    pub fn gen_range(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * self.next_f32()
+    }
+}
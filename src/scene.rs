@@ -0,0 +1,93 @@
+use slotmap::SecondaryMap;
+
+use crate::node::next_launch;
+use crate::sim::Simulation;
+use crate::{NodeId, BAR_TIME};
+
+/// An enable-mask over the graph: muted nodes neither spawn nor pass signals.
+#[derive(Clone, Default)]
+pub struct Scene {
+    pub name: String,
+    pub muted: SecondaryMap<NodeId, ()>,
+}
+
+/// One entry of the song arrangement: `scene` plays for `bars` bars.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Section {
+    pub scene: usize,
+    pub bars: u32,
+}
+
+impl Simulation {
+    /// True if `id` or a macro containing it is muted in the current scene.
+    pub fn is_muted(&self, id: NodeId) -> bool {
+        let muted = &self.scenes[self.scene].muted;
+        let mut node = Some(id);
+        while let Some(id) = node {
+            if muted.contains_key(id) {
+                return true;
+            }
+            node = self.nodes[id].parent;
+        }
+        false
+    }
+    pub fn toggle_muted(&mut self, id: NodeId) {
+        let muted = &mut self.scenes[self.scene].muted;
+        if muted.remove(id).is_none() {
+            muted.insert(id, ());
+        }
+    }
+    /// Adds a copy of the current scene and returns its index.
+    pub fn add_scene(&mut self) -> usize {
+        let mut scene = self.scenes[self.scene].clone();
+        scene.name = ((b'A' + self.scenes.len() as u8 % 26) as char).to_string();
+        self.scenes.push(scene);
+        self.scenes.len() - 1
+    }
+    /// Switches to `scene` on the bar after `time`, or the next launch quantum
+    /// boundary when that spans several bars.
+    pub fn queue_scene(&mut self, scene: usize, time: f32) {
+        let at = next_launch(time, self.launch_quantum.max(1.0), 0.0);
+        self.queued_scene = Some((scene, at));
+    }
+    pub fn arrangement_bars(&self) -> u32 {
+        self.arrangement.iter().map(|section| section.bars).sum()
+    }
+    /// Scene the arrangement plays at `time`, `None` past the end.
+    pub fn arrangement_scene(&self, time: f32) -> Option<usize> {
+        let bar = (time / BAR_TIME).floor().max(0.0) as u32;
+        let mut end = 0;
+        self.arrangement.iter().find_map(|section| {
+            end += section.bars;
+            (bar < end).then_some(section.scene)
+        })
+    }
+    /// Plays the arrangement from the top.
+    pub fn play_song(&mut self) {
+        self.rewind();
+        self.follow_arrangement = true;
+        self.paused = false;
+    }
+    pub fn update_scene(&mut self) {
+        if self.follow_arrangement {
+            match self.arrangement_scene(self.time) {
+                Some(scene) => {
+                    self.edited |= scene != self.scene;
+                    self.scene = scene;
+                }
+                None => {
+                    self.follow_arrangement = false;
+                    self.stop();
+                }
+            }
+            return;
+        }
+        if let Some((scene, at)) = self.queued_scene {
+            if at <= self.time {
+                self.scene = scene;
+                self.queued_scene = None;
+                self.edited = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::node::Payload;
+use crate::{BAR_TIME, BEATS_PER_BAR};
+
+/// A node whose behaviour on signal arrival is an expression, e.g.
+/// `bar % 2 == 1` or `count % outs`. A boolean result passes or blocks the
+/// signal, a number picks the outgoing edge with that index. The edge list
+/// is only exposed as its length `outs`: an expression can't do anything
+/// with an edge but pick it, and picking by index works because
+/// `Simulation::next_edges` lists the edges in the same order every run.
+#[derive(Clone, PartialEq)]
+pub struct Script {
+    pub source: String,
+    pub program: Result<Expr, String>,
+    pub count: u32,
+    pub error: Option<String>,
+}
+impl Script {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            program: parse(source).map_err(|e| e.to_string()),
+            count: 0,
+            error: None,
+        }
+    }
+    pub fn recompile(&mut self) {
+        self.program = parse(&self.source).map_err(|e| e.to_string());
+        self.error = None;
+    }
+    /// Counts the arrival and returns the indices of the outgoing edges
+    /// the signal continues on. A failing script blocks the signal.
+    pub fn run(&mut self, time: f32, payload: &Payload, outs: usize) -> Vec<usize> {
+        self.count += 1;
+        self.eval(time, payload, outs).unwrap_or_else(|e| {
+            self.error = Some(e.to_string());
+            vec![]
+        })
+    }
+    fn eval(&self, time: f32, payload: &Payload, outs: usize) -> Result<Vec<usize>> {
+        let program = self.program.as_ref().map_err(|e| anyhow!("{e}"))?;
+        let beats = (time / BAR_TIME * BEATS_PER_BAR as f32).floor().max(0.0) as u32;
+        let env = Env {
+            bar: (beats / BEATS_PER_BAR + 1) as f32,
+            beat: (beats % BEATS_PER_BAR + 1) as f32,
+            count: self.count as f32,
+            vel: payload.velocity,
+            transpose: payload.transpose as f32,
+            tag: payload.tag.map_or(-1.0, |c| c as u32 as f32),
+            outs: outs as f32,
+            time: time / BAR_TIME,
+        };
+        Ok(match program.eval(&env)? {
+            Value::Bool(true) => (0..outs).collect(),
+            Value::Bool(false) => vec![],
+            Value::Num(_) if outs == 0 => vec![],
+            Value::Num(n) => vec![(n.floor() as i64).rem_euclid(outs as i64) as usize],
+        })
+    }
+}
+
+const VARIABLES: [&str; 8] = [
+    "bar",
+    "beat",
+    "count",
+    "vel",
+    "transpose",
+    "tag",
+    "outs",
+    "time",
+];
+
+struct Env {
+    bar: f32,
+    beat: f32,
+    count: f32,
+    vel: f32,
+    transpose: f32,
+    tag: f32,
+    outs: f32,
+    time: f32,
+}
+impl Env {
+    fn get(&self, name: &str) -> Result<f32> {
+        Ok(match name {
+            "bar" => self.bar,
+            "beat" => self.beat,
+            "count" => self.count,
+            "vel" => self.vel,
+            "transpose" => self.transpose,
+            "tag" => self.tag,
+            "outs" => self.outs,
+            "time" => self.time,
+            _ => bail!("unknown variable `{name}`"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Value {
+    Num(f32),
+    Bool(bool),
+}
+impl Value {
+    fn num(self) -> Result<f32> {
+        match self {
+            Value::Num(n) => Ok(n),
+            Value::Bool(_) => bail!("expected a number, found a boolean"),
+        }
+    }
+    fn bool(self) -> Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(b),
+            Value::Num(_) => bail!("expected a boolean, found a number"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+impl Op {
+    /// Binding power, higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::Eq | Op::Ne => 3,
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => 4,
+            Op::Add | Op::Sub => 5,
+            Op::Mul | Op::Div | Op::Rem => 6,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Lit(Value),
+    Var(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+impl Expr {
+    fn eval(&self, env: &Env) -> Result<Value> {
+        Ok(match self {
+            Expr::Lit(value) => *value,
+            Expr::Var(name) => Value::Num(env.get(name)?),
+            Expr::Neg(e) => Value::Num(-e.eval(env)?.num()?),
+            Expr::Not(e) => Value::Bool(!e.eval(env)?.bool()?),
+            Expr::Binary(Op::And, a, b) => {
+                Value::Bool(a.eval(env)?.bool()? && b.eval(env)?.bool()?)
+            }
+            Expr::Binary(Op::Or, a, b) => Value::Bool(a.eval(env)?.bool()? || b.eval(env)?.bool()?),
+            Expr::Binary(op @ (Op::Eq | Op::Ne), a, b) => {
+                let equal = match (a.eval(env)?, b.eval(env)?) {
+                    (Value::Num(a), Value::Num(b)) => a == b,
+                    (Value::Bool(a), Value::Bool(b)) => a == b,
+                    _ => bail!("cannot compare a number with a boolean"),
+                };
+                Value::Bool(equal == (*op == Op::Eq))
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(env)?.num()?, b.eval(env)?.num()?);
+                match op {
+                    Op::Add => Value::Num(a + b),
+                    Op::Sub => Value::Num(a - b),
+                    Op::Mul => Value::Num(a * b),
+                    Op::Div => Value::Num(a / b),
+                    Op::Rem => Value::Num(a.rem_euclid(b)),
+                    Op::Lt => Value::Bool(a < b),
+                    Op::Le => Value::Bool(a <= b),
+                    Op::Gt => Value::Bool(a > b),
+                    Op::Ge => Value::Bool(a >= b),
+                    Op::And | Op::Or | Op::Eq | Op::Ne => unreachable!(),
+                }
+            }
+            Expr::If(cond, then, otherwise) => {
+                if cond.eval(env)?.bool()? {
+                    then.eval(env)?
+                } else {
+                    otherwise.eval(env)?
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Num(f32),
+    Ident(String),
+    Op(Op),
+    Not,
+    Question,
+    Colon,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '0'..='9' | '.' => {
+                let mut text = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    text.push(c);
+                }
+                Token::Num(text.parse().map_err(|_| anyhow!("bad number `{text}`"))?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut text = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    text.push(c);
+                }
+                Token::Ident(text)
+            }
+            '\'' => {
+                let tag = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("unterminated tag literal"))?;
+                if chars.next() != Some('\'') {
+                    bail!("unterminated tag literal");
+                }
+                Token::Num(tag as u32 as f32)
+            }
+            '+' => Token::Op(Op::Add),
+            '-' => Token::Op(Op::Sub),
+            '*' => Token::Op(Op::Mul),
+            '/' => Token::Op(Op::Div),
+            '%' => Token::Op(Op::Rem),
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Eq),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '&' if chars.next_if_eq(&'&').is_some() => Token::Op(Op::And),
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Op(Op::Or),
+            '?' => Token::Question,
+            ':' => Token::Colon,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c => bail!("unexpected `{c}`"),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+pub fn parse(source: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    let expr = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        bail!("unexpected {token:?}");
+    }
+    Ok(expr)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow!("unexpected end"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => bail!("expected {expected:?}, found {token:?}"),
+        }
+    }
+    /// `cond ? a : b` binds loosest and nests to the right.
+    fn expr(&mut self) -> Result<Expr> {
+        let cond = self.binary(0)?;
+        if self.peek() != Some(&Token::Question) {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.expr()?;
+        self.expect(Token::Colon)?;
+        let otherwise = self.expr()?;
+        Ok(Expr::If(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(op.precedence())?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+    fn unary(&mut self) -> Result<Expr> {
+        Ok(match self.next()? {
+            Token::Op(Op::Sub) => Expr::Neg(Box::new(self.unary()?)),
+            Token::Not => Expr::Not(Box::new(self.unary()?)),
+            Token::Num(n) => Expr::Lit(Value::Num(n)),
+            Token::Ident(name) if name == "true" => Expr::Lit(Value::Bool(true)),
+            Token::Ident(name) if name == "false" => Expr::Lit(Value::Bool(false)),
+            Token::Ident(name) if VARIABLES.contains(&name.as_str()) => Expr::Var(name),
+            Token::Ident(name) => bail!("unknown variable `{name}`"),
+            Token::Open => {
+                let expr = self.expr()?;
+                self.expect(Token::Close)?;
+                expr
+            }
+            token => bail!("unexpected {token:?}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script: &mut Script, time: f32, outs: usize) -> Vec<usize> {
+        script.run(time * BAR_TIME, &Payload::default(), outs)
+    }
+
+    #[test]
+    fn precedence() {
+        let mut script = Script::new("1 + 2 * 3 == 7 && !(2 < 1) || false");
+        assert_eq!(run(&mut script, 0.0, 2), vec![0, 1]);
+        let mut script = Script::new("-2 * 3 + 10 % 4");
+        assert_eq!(run(&mut script, 0.0, 5), vec![1]);
+    }
+
+    #[test]
+    fn conditional_nests_to_the_right() {
+        let mut script = Script::new("count == 1 ? 0 : count == 2 ? 1 : 2");
+        let picks: Vec<Vec<usize>> = (0..3).map(|_| run(&mut script, 0.0, 3)).collect();
+        assert_eq!(picks, [[0], [1], [2]]);
+    }
+
+    #[test]
+    fn numbers_pick_an_edge_modulo_outs() {
+        let mut script = Script::new("count % outs");
+        let picks: Vec<Vec<usize>> = (0..3).map(|_| run(&mut script, 0.0, 3)).collect();
+        assert_eq!(picks, [[1], [2], [0]]);
+        assert!(run(&mut script, 0.0, 0).is_empty());
+    }
+
+    #[test]
+    fn bar_and_beat_are_one_based() {
+        let mut script = Script::new("bar == 2 && beat == 2");
+        assert_eq!(run(&mut script, 1.25, 1), vec![0]);
+        assert!(run(&mut script, 0.25, 1).is_empty());
+    }
+
+    #[test]
+    fn tags_compare_with_literals() {
+        let mut script = Script::new("tag == 'a'");
+        let payload = Payload {
+            tag: Some('a'),
+            ..Payload::default()
+        };
+        assert_eq!(script.run(0.0, &payload, 1), vec![0]);
+        assert!(run(&mut script, 0.0, 1).is_empty());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for source in ["1 +", "(1", "1 ? 2", "foo", "1 = 2", "'a", "1 2"] {
+            assert!(parse(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn type_errors_block_and_report() {
+        let mut script = Script::new("1 && true");
+        assert!(run(&mut script, 0.0, 2).is_empty());
+        assert!(script.error.is_some());
+    }
+}
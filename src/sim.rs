@@ -0,0 +1,712 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use slotmap::{SecondaryMap, SlotMap};
+
+use crate::edge::Edge;
+use crate::node::{Node, NodeKind, Payload};
+use crate::record::Recorder;
+use crate::rng::Rng;
+use crate::scene::{Scene, Section};
+use crate::util::IndexOf as _;
+use crate::{
+    EdgeId, NodeId, SignalId, BAR_TIME, BEATS_PER_BAR, MAX_CATCH_UP, SIGNAL_WARNING_TIME, TICK_TIME,
+};
+
+#[derive(Clone)]
+pub struct Signal {
+    pub cur_edge: EdgeId,
+    pub start_time: f32,
+    pub born: f32,
+    pub hops: u32,
+    pub payload: Payload,
+    /// When the signal reaches the end of its edge, infinite while stalled.
+    pub arrival: f32,
+    /// Waiting on an edge that points back in time.
+    stalled: bool,
+    /// Matches the queue entry that is still current for this signal.
+    seq: u64,
+}
+impl Signal {
+    pub fn new(cur_edge: EdgeId, start_time: f32, born: f32, hops: u32, payload: Payload) -> Self {
+        Self {
+            cur_edge,
+            start_time,
+            born,
+            hops,
+            payload,
+            arrival: f32::INFINITY,
+            stalled: false,
+            seq: 0,
+        }
+    }
+}
+
+/// An entry in the arrival queue. Simultaneous arrivals are handled in
+/// order of their edge's key, and signals on the same edge in the order
+/// they were sent. Outgoing signals are sent in edge key order too, so the
+/// whole run only depends on the patch and the seed, never on the history
+/// of the signal or adjacency storage.
+#[derive(Clone)]
+struct Scheduled {
+    arrival: f32,
+    edge: EdgeId,
+    seq: u64,
+    signal: SignalId,
+}
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.arrival
+            .total_cmp(&other.arrival)
+            .then(self.edge.cmp(&other.edge))
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Scheduled {}
+
+#[derive(Clone, PartialEq)]
+pub struct Limits {
+    pub max_hops: u32,
+    pub ttl_bars: f32,
+    pub max_signals: u32,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_hops: 64,
+            ttl_bars: 64.0,
+            max_signals: 2000,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Adjlist {
+    pub incoming: Vec<(NodeId, EdgeId)>,
+    pub outgoing: Vec<(NodeId, EdgeId)>,
+}
+
+/// A node firing, `sample` is set when it plays one.
+#[derive(Clone, Copy)]
+pub struct TriggerEvent {
+    pub time: f32,
+    pub node: NodeId,
+    pub sample: Option<usize>,
+    pub payload: Payload,
+}
+
+/// The patch and everything that moves through it, independent of any
+/// window, input or audio backend.
+#[derive(Clone, Default)]
+pub struct Simulation {
+    pub nodes: SlotMap<NodeId, Node>,
+    pub edges: SlotMap<EdgeId, Edge>,
+    pub adj: SecondaryMap<NodeId, Adjlist>,
+
+    pub signals: SlotMap<SignalId, Signal>,
+    /// Pending arrivals, entries of moved or removed signals are skipped.
+    queue: BinaryHeap<Reverse<Scheduled>>,
+    next_seq: u64,
+    pub limits: Limits,
+    pub dropped_signals: usize,
+    pub last_drop: f32,
+    /// Always `tick * TICK_TIME`.
+    pub time: f32,
+    pub tick: u64,
+    /// Frame time not yet simulated.
+    pub lag: f32,
+    pub paused: bool,
+    pub launch_quantum: f32,
+    pub scenes: Vec<Scene>,
+    pub scene: usize,
+    /// Scene to switch to and the time of the switch.
+    pub queued_scene: Option<(usize, f32)>,
+    pub arrangement: Vec<Section>,
+    /// Let the arrangement pick the scene instead of queued switches.
+    pub follow_arrangement: bool,
+    pub recorder: Recorder,
+    /// Seed of the musical random stream, restored on every rewind.
+    pub seed: u64,
+    /// Keep the seed across rewinds instead of rolling a new one.
+    pub seed_locked: bool,
+    pub rng: Rng,
+    /// Node the debugger stopped at, later arrivals wait until play resumes.
+    pub halted: Option<NodeId>,
+    /// Run to the next arrival on the next step.
+    pub single_step: bool,
+    stepping: bool,
+    /// Set when the patch, the seed or the mutes change, cleared by the
+    /// preview once it has caught up.
+    pub edited: bool,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self {
+            launch_quantum: 1.0,
+            scenes: vec![Scene {
+                name: "A".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+    pub fn add_node(&mut self, node: Node) -> NodeId {
+        self.edited = true;
+        let id = self.nodes.insert(node);
+        self.adj.insert(id, Default::default());
+        id
+    }
+    pub fn add_edge(&mut self, edge: Edge) -> EdgeId {
+        self.edited = true;
+        let (u, v) = edge.nodes;
+        let id = self.edges.insert(edge);
+        self.adj[u].outgoing.push((v, id));
+        self.adj[v].incoming.push((u, id));
+        id
+    }
+    pub fn remove_node(&mut self, node: NodeId) {
+        self.edited = true;
+        for child in self.descendants(node).into_iter().rev() {
+            self.remove_node(child);
+        }
+        self.remove_port(node);
+        let adjlist = self.adj.remove(node).unwrap();
+        for (u, edge) in adjlist.incoming {
+            let pos = self.adj[u].outgoing.index_of(&(node, edge)).unwrap();
+            self.adj[u].outgoing.swap_remove(pos);
+            self.edges.remove(edge);
+        }
+
+        for (u, edge) in adjlist.outgoing {
+            let pos = self.adj[u].incoming.index_of(&(node, edge)).unwrap();
+            self.adj[u].incoming.swap_remove(pos);
+            self.edges.remove(edge);
+        }
+
+        self.nodes.remove(node);
+        self.drop_orphans();
+    }
+    pub fn remove_edge(&mut self, edge: EdgeId) -> Edge {
+        self.edited = true;
+        let removed = self.edges.remove(edge).unwrap();
+        let (u, v) = removed.nodes;
+        let idx = self.adj[u].outgoing.index_of(&(v, edge)).unwrap();
+        self.adj[u].outgoing.swap_remove(idx);
+        let idx = self.adj[v].incoming.index_of(&(u, edge)).unwrap();
+        self.adj[v].incoming.swap_remove(idx);
+        self.drop_orphans();
+        removed
+    }
+    /// Removes signals whose edge is gone, their queue entries are skipped.
+    fn drop_orphans(&mut self) {
+        let edges = &self.edges;
+        self.signals
+            .retain(|_, signal| edges.contains_key(signal.cur_edge));
+    }
+    pub fn push_signal(&mut self, mut signal: Signal) {
+        let edge = &self.edges[signal.cur_edge];
+        if !edge.enabled || self.rng.next_f32() >= edge.probability {
+            return;
+        }
+        signal.start_time += edge.delay * BAR_TIME;
+        if self.signals.len() >= self.limits.max_signals as usize {
+            self.dropped_signals += 1;
+            self.last_drop = self.time;
+            return;
+        }
+        let id = self.signals.insert(signal);
+        self.schedule(id);
+    }
+    /// Computes the arrival of signal `id` from the current geometry and queues it.
+    fn schedule(&mut self, id: SignalId) {
+        let signal = &mut self.signals[id];
+        // any earlier queue entry is stale from here on
+        self.next_seq += 1;
+        signal.seq = self.next_seq;
+        let Some(duration) = self.edges[signal.cur_edge].duration(&self.nodes) else {
+            signal.arrival = f32::INFINITY;
+            signal.stalled = true;
+            return;
+        };
+        if signal.stalled {
+            // a stalled signal sets off again from where it waited
+            signal.start_time = signal.start_time.max(self.time);
+            signal.stalled = false;
+        }
+        signal.arrival = signal.start_time + duration;
+        self.queue.push(Reverse(Scheduled {
+            arrival: signal.arrival,
+            edge: signal.cur_edge,
+            seq: signal.seq,
+            signal: id,
+        }));
+    }
+    /// Recomputes arrivals on the edges whose timing depends on `node`,
+    /// call after moving it.
+    pub fn reschedule(&mut self, node: NodeId) {
+        let parent = self.nodes.get(node).and_then(|node| node.parent);
+        let edges: Vec<EdgeId> = self
+            .edges
+            .iter()
+            .filter(|(_, edge)| {
+                let (u, v) = edge.nodes;
+                u == node || v == node || Some(u) == parent || Some(v) == parent
+            })
+            .map(|(id, _)| id)
+            .collect();
+        self.reschedule_edges(&edges);
+    }
+    /// Recomputes arrivals on `edges`, call after changing their kind or ports.
+    pub fn reschedule_edges(&mut self, edges: &[EdgeId]) {
+        self.edited = true;
+        let ids: Vec<SignalId> = self
+            .signals
+            .iter()
+            .filter(|(_, signal)| edges.contains(&signal.cur_edge))
+            .map(|(id, _)| id)
+            .collect();
+        for id in ids {
+            self.schedule(id);
+        }
+    }
+    pub fn kill_signals(&mut self) {
+        self.signals.clear();
+        self.queue.clear();
+        self.dropped_signals = 0;
+    }
+    pub fn signal_warning(&self) -> bool {
+        self.dropped_signals > 0 && self.time - self.last_drop < SIGNAL_WARNING_TIME
+    }
+    pub fn stop(&mut self) {
+        self.paused = true;
+        self.halted = None;
+        self.recorder.recording = false;
+        self.recorder.replay = None;
+        self.signals.clear();
+        self.queue.clear();
+    }
+    pub fn reseed(&mut self, seed: u64) {
+        self.edited = true;
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+    pub fn reroll(&mut self) {
+        let seed = self.rng.next_u64();
+        self.reseed(seed);
+    }
+    pub fn rewind(&mut self) {
+        if self.seed_locked {
+            self.reseed(self.seed);
+        } else {
+            self.reroll();
+        }
+        self.time = 0.0;
+        self.tick = 0;
+        self.lag = 0.0;
+        self.signals.clear();
+        self.queue.clear();
+        self.queued_scene = None;
+        self.halted = None;
+        for node in self.nodes.values_mut() {
+            node.rewind();
+        }
+    }
+    /// Current position as 1-based (bar, beat).
+    pub fn bar_beat(&self) -> (u32, u32) {
+        let beats = (self.time / BAR_TIME * BEATS_PER_BAR as f32)
+            .floor()
+            .max(0.0) as u32;
+        (beats / BEATS_PER_BAR + 1, beats % BEATS_PER_BAR + 1)
+    }
+    /// Advances the clock by `dt` seconds unless paused, in whole ticks so the
+    /// result doesn't depend on the frame rate, and returns the nodes that fired.
+    pub fn step(&mut self, dt: f32) -> Vec<TriggerEvent> {
+        if self.single_step {
+            self.single_step = false;
+            return self.step_arrival();
+        }
+        if !self.paused {
+            self.halted = None;
+        }
+        if self.paused {
+            // actions taken while paused still happen now
+            return self.process();
+        }
+        self.lag = (self.lag + dt).min(MAX_CATCH_UP);
+        let mut events = vec![];
+        while self.lag >= TICK_TIME {
+            self.lag -= TICK_TIME;
+            events.extend(self.advance());
+            if self.paused {
+                // stopped at a breakpoint
+                self.lag = 0.0;
+                break;
+            }
+        }
+        events
+    }
+    /// Pauses and runs until the next signal arrives somewhere, giving up
+    /// after `MAX_CATCH_UP` when nothing is on its way.
+    pub fn step_arrival(&mut self) -> Vec<TriggerEvent> {
+        self.paused = true;
+        self.halted = None;
+        self.stepping = true;
+        // arrivals left over from the tick the last halt happened in
+        let mut events = self.propagate();
+        let end = self.tick + (MAX_CATCH_UP / TICK_TIME) as u64;
+        while self.stepping && self.tick < end {
+            events.extend(self.advance());
+        }
+        self.stepping = false;
+        events
+    }
+    /// Moves forward exactly one tick.
+    pub fn advance(&mut self) -> Vec<TriggerEvent> {
+        self.tick += 1;
+        self.time = self.tick as f32 * TICK_TIME;
+        self.process()
+    }
+    fn process(&mut self) -> Vec<TriggerEvent> {
+        self.update_replay();
+        self.update_scene();
+        self.spawn();
+        self.propagate()
+    }
+    fn spawn(&mut self) {
+        let muted: Vec<NodeId> = self.nodes.keys().filter(|&id| self.is_muted(id)).collect();
+        let mut spawned = vec![];
+        for (id, node) in &mut self.nodes {
+            let mut due = vec![];
+            match &mut node.kind {
+                NodeKind::Spawner {
+                    bar_delay,
+                    next_spawn,
+                    payload,
+                    ..
+                } => {
+                    if *next_spawn <= self.time {
+                        due.push((*next_spawn, *payload));
+                        *next_spawn += *bar_delay * BAR_TIME;
+                    }
+                }
+                NodeKind::Euclid(euclid) => euclid.poll(self.time, &mut due),
+                NodeKind::Pattern(pattern) => pattern.poll(self.time, &mut due),
+                NodeKind::Trigger(trigger) => trigger.poll(self.time, &mut due),
+                _ => continue,
+            };
+            if muted.contains(&id) {
+                continue;
+            }
+            let mut outgoing: Vec<EdgeId> = self.adj[id].outgoing.iter().map(|&(_, e)| e).collect();
+            outgoing.sort();
+            for (start_time, payload) in due {
+                for &edge_id in &outgoing {
+                    spawned.push(Signal::new(edge_id, start_time, start_time, 0, payload));
+                }
+            }
+        }
+        for signal in spawned {
+            self.push_signal(signal);
+        }
+    }
+    /// Handles every arrival that is due, in queue order.
+    fn propagate(&mut self) -> Vec<TriggerEvent> {
+        let muted: Vec<NodeId> = self.nodes.keys().filter(|&id| self.is_muted(id)).collect();
+        let mut triggers = vec![];
+        while let Some(Reverse(next)) = self.queue.peek() {
+            if next.arrival > self.time || self.halted.is_some() {
+                break;
+            }
+            let Reverse(Scheduled {
+                seq, signal: id, ..
+            }) = self.queue.pop().unwrap();
+            if self.signals.get(id).map(|signal| signal.seq) != Some(seq) {
+                continue;
+            }
+            let signal = self.signals.remove(id).unwrap();
+            let Signal {
+                cur_edge: cur,
+                arrival: start_time,
+                born,
+                hops,
+                mut payload,
+                ..
+            } = signal;
+            let to = self.edges[cur].nodes.1;
+            if self.stepping || self.nodes[to].breakpoint {
+                self.stepping = false;
+                self.paused = true;
+                self.halted = Some(to);
+            }
+            if muted.contains(&to)
+                || matches!(self.nodes[to].kind, NodeKind::Switch { open: false, .. })
+            {
+                continue;
+            }
+            let fired = self.nodes[to].try_fire(start_time);
+            if fired {
+                let sample = match self.nodes[to].kind {
+                    NodeKind::Sample(sample) => Some(sample),
+                    _ => None,
+                };
+                triggers.push(TriggerEvent {
+                    time: start_time,
+                    node: to,
+                    sample,
+                    payload,
+                });
+            }
+            let expired = hops >= self.limits.max_hops
+                || start_time - born >= self.limits.ttl_bars * BAR_TIME;
+            if expired || (!fired && self.nodes[to].merge_downstream) {
+                continue;
+            }
+            if let NodeKind::Transform(transform) = &self.nodes[to].kind {
+                transform.apply(&mut payload);
+            }
+            let mut next = self.next_edges(to, cur);
+            if let NodeKind::Script(script) = &mut self.nodes[to].kind {
+                let chosen = script.run(start_time, &payload, next.len());
+                next = chosen.into_iter().map(|i| next[i]).collect();
+            }
+            for e in next {
+                self.push_signal(Signal::new(e, start_time, born, hops + 1, payload));
+            }
+        }
+        triggers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Merge;
+    use crate::PX_PER_BAR;
+
+    fn spawner(sim: &mut Simulation, bars: f32) -> NodeId {
+        let mut node = Node::new(0.0, 0.0);
+        node.kind = NodeKind::spawner(bars);
+        sim.add_node(node)
+    }
+    /// A sample node `bars` bars to the right of the origin.
+    fn sample(sim: &mut Simulation, bars: f32, sample: usize) -> NodeId {
+        let mut node = Node::new(bars * PX_PER_BAR, 0.0);
+        node.kind = NodeKind::Sample(sample);
+        sim.add_node(node)
+    }
+    fn run(sim: &mut Simulation, bars: f32) -> Vec<TriggerEvent> {
+        let mut events = vec![];
+        while sim.time < bars * BAR_TIME {
+            events.extend(sim.advance());
+        }
+        events
+    }
+
+    #[test]
+    fn chains_arrive_at_the_sum_of_their_edges() {
+        let mut sim = Simulation::new();
+        let mut prev = spawner(&mut sim, 4.0);
+        for i in 1..=7 {
+            let next = sample(&mut sim, 0.1 * i as f32, i);
+            sim.add_edge(Edge::new(prev, next));
+            prev = next;
+        }
+        let events = run(&mut sim, 1.0);
+        assert_eq!(events.len(), 7);
+        for (i, event) in events.iter().enumerate() {
+            let expected = 0.1 * (i + 1) as f32 * BAR_TIME;
+            assert!((event.time - expected).abs() < 1e-4, "{}", event.time);
+        }
+    }
+
+    #[test]
+    fn stalled_signals_set_off_when_unstalled() {
+        let mut sim = Simulation::new();
+        let s = spawner(&mut sim, 4.0);
+        let v = sample(&mut sim, -1.0, 0);
+        sim.add_edge(Edge::new(s, v));
+        run(&mut sim, 0.5);
+        assert_eq!(sim.signals.len(), 1);
+        sim.nodes[v].pos.x = PX_PER_BAR;
+        sim.reschedule(v);
+        let events = run(&mut sim, 2.0);
+        assert_eq!(events.len(), 1);
+        assert!((events[0].time - 1.5 * BAR_TIME).abs() < 1e-4);
+    }
+
+    #[test]
+    fn signals_stall_when_their_edge_turns_back() {
+        let mut sim = Simulation::new();
+        let s = spawner(&mut sim, 4.0);
+        let v = sample(&mut sim, 1.0, 0);
+        sim.add_edge(Edge::new(s, v));
+        run(&mut sim, 0.5);
+        sim.nodes[v].pos.x = -PX_PER_BAR;
+        sim.reschedule(v);
+        assert!(run(&mut sim, 3.0).is_empty());
+        assert_eq!(sim.signals.len(), 1);
+        assert_eq!(sim.signals.values().next().unwrap().arrival, f32::INFINITY);
+    }
+
+    /// The same patch, in the second copy one edge is removed and added
+    /// back so the adjacency lists are in a different order.
+    fn fan_out(reorder: bool) -> Simulation {
+        let mut sim = Simulation::new();
+        let s = spawner(&mut sim, 0.5);
+        let d = sample(&mut sim, 1.0, 9);
+        sim.nodes[d].merge = Merge::Off;
+        let mids: Vec<NodeId> = (0..3).map(|i| sample(&mut sim, 0.5, i)).collect();
+        let mut out = vec![];
+        for &mid in &mids {
+            out.push(sim.add_edge(Edge::new(s, mid)));
+            let e = sim.add_edge(Edge::new(mid, d));
+            sim.edges[e].probability = 0.7;
+        }
+        if reorder {
+            let edge = sim.remove_edge(out[0]);
+            sim.add_edge(edge);
+        }
+        for e in sim.edges.keys().collect::<Vec<_>>() {
+            if sim.edges[e].nodes.0 == s {
+                sim.edges[e].probability = 0.5;
+            }
+        }
+        sim.reseed(42);
+        sim
+    }
+
+    #[test]
+    fn simultaneous_arrivals_ignore_adjacency_order() {
+        let (mut a, mut b) = (fan_out(false), fan_out(true));
+        let s = a.nodes.keys().next().unwrap();
+        assert!(a.adj[s].outgoing != b.adj[s].outgoing);
+        let fired = |sim: &mut Simulation| -> Vec<(f32, NodeId, Option<usize>)> {
+            run(sim, 16.0)
+                .iter()
+                .map(|e| (e.time, e.node, e.sample))
+                .collect()
+        };
+        let events = fired(&mut a);
+        assert!(events.len() > 16);
+        assert_eq!(events, fired(&mut b));
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let mut sim = Simulation::new();
+        let s = spawner(&mut sim, 0.25);
+        for i in 0..4 {
+            let v = sample(&mut sim, 0.5, i);
+            let e = sim.add_edge(Edge::new(s, v));
+            sim.edges[e].probability = 0.5;
+        }
+        sim.reseed(7);
+        let schedule = sim.predict_from_top(8.0);
+        assert!(!schedule.is_empty());
+        assert_eq!(schedule, sim.predict_from_top(8.0));
+    }
+
+    #[test]
+    fn disabled_and_improbable_edges_drop_signals() {
+        let mut sim = Simulation::new();
+        let s = spawner(&mut sim, 1.0);
+        let a = sample(&mut sim, 0.5, 0);
+        let b = sample(&mut sim, 0.5, 1);
+        let e = sim.add_edge(Edge::new(s, a));
+        sim.edges[e].probability = 0.0;
+        let e = sim.add_edge(Edge::new(s, b));
+        sim.edges[e].enabled = false;
+        assert!(run(&mut sim, 4.0).is_empty());
+        assert!(sim.signals.is_empty());
+    }
+
+    #[test]
+    fn paused_clock_stands_still() {
+        let mut sim = Simulation::new();
+        sim.paused = true;
+        sim.step(1.0);
+        assert_eq!(sim.tick, 0);
+        sim.paused = false;
+        sim.step(TICK_TIME * 3.5);
+        assert_eq!(sim.tick, 3);
+    }
+
+    /// A spawner with two edges that arrive in the same tick, half a bar out.
+    fn twins(sim: &mut Simulation) -> (NodeId, NodeId) {
+        let s = spawner(sim, 4.0);
+        let a = sample(sim, 0.5, 0);
+        let b = sample(sim, 0.5, 1);
+        sim.add_edge(Edge::new(s, a));
+        sim.add_edge(Edge::new(s, b));
+        (a, b)
+    }
+
+    #[test]
+    fn single_steps_stop_at_each_arrival() {
+        let mut sim = Simulation::new();
+        let (a, b) = twins(&mut sim);
+        let arrival = (0.5 * BAR_TIME / TICK_TIME).round() as u64;
+        let events = sim.step_arrival();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].node, a);
+        assert_eq!((sim.tick, sim.halted), (arrival, Some(a)));
+        assert!(sim.paused);
+        // the twin arrives in the same tick, so the clock stays put
+        let events = sim.step_arrival();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].node, b);
+        assert_eq!((sim.tick, sim.halted), (arrival, Some(b)));
+        // skips the quiet ticks up to the next spawn's arrival
+        let events = sim.step_arrival();
+        assert_eq!(events.len(), 1);
+        assert_eq!((sim.tick, sim.halted), (arrival * 9, Some(a)));
+    }
+
+    #[test]
+    fn breakpoints_hold_back_the_rest_of_the_tick() {
+        let mut sim = Simulation::new();
+        let (a, b) = twins(&mut sim);
+        sim.nodes[a].breakpoint = true;
+        let events = sim.step(BAR_TIME);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].node, a);
+        assert_eq!(sim.halted, Some(a));
+        assert!(sim.paused);
+        assert_eq!(sim.signals.len(), 1);
+        // stays halted while paused
+        assert!(sim.step(BAR_TIME).is_empty());
+        assert_eq!(sim.signals.len(), 1);
+
+        sim.paused = false;
+        let events = sim.step(TICK_TIME);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].node, b);
+        assert!((events[0].time - 0.5 * BAR_TIME).abs() < 1e-4);
+        assert_eq!(sim.halted, None);
+        assert!(sim.signals.is_empty());
+    }
+
+    #[test]
+    fn removing_an_edge_drops_its_signals() {
+        let mut sim = Simulation::new();
+        let s = spawner(&mut sim, 1.0);
+        let v = sample(&mut sim, 2.0, 0);
+        let e = sim.add_edge(Edge::new(s, v));
+        run(&mut sim, 0.5);
+        assert_eq!(sim.signals.len(), 1);
+        sim.remove_edge(e);
+        assert!(sim.signals.is_empty());
+        assert!(run(&mut sim, 3.0).is_empty());
+    }
+}
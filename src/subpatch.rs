@@ -0,0 +1,38 @@
+use glam::{vec2, Vec2};
+use macroquad::window::{screen_height, screen_width};
+
+use crate::node::Node;
+use crate::{Mode, NodeId, State};
+
+impl State {
+    pub fn in_view(&self, node: NodeId) -> bool {
+        self.sim.nodes[node].parent == self.view
+    }
+    /// Timeline position of the open macro's interior origin.
+    pub fn view_offset(&self) -> f32 {
+        let mut offset = 0.0;
+        let mut view = self.view;
+        while let Some(id) = view {
+            offset += self.sim.nodes[id].pos.x;
+            view = self.sim.nodes[id].parent;
+        }
+        offset
+    }
+    pub fn add_node_here(&mut self, pos: Vec2) -> NodeId {
+        let mut node = Node::new(pos.x, pos.y);
+        node.parent = self.view;
+        self.sim.add_node(node)
+    }
+    pub fn set_view(&mut self, view: Option<NodeId>) {
+        let center = vec2(screen_width(), screen_height()) / 2.0;
+        self.camera_pos = match self.view.filter(|_| view.is_none()) {
+            // leaving to the top level, look at the macro we came from
+            Some(id) => self.sim.nodes[id].pos - center,
+            None => -center,
+        };
+        self.view = view;
+        self.selection = None;
+        self.group.clear();
+        self.mode = Mode::default();
+    }
+}
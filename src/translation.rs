@@ -12,21 +12,20 @@ pub trait Translatable: Sized {
 }
 impl Translatable for Node {
     fn translate(&self, camera_pos: Vec2) -> Translation<'_, Self> {
-        return Translation {
+        Translation {
             pos: self.pos - camera_pos,
             inner: self
         }
-        
     }
 }
 impl Translatable for Vec2 {
     fn translate(&self, camera_pos: Vec2) -> Translation<'_, Self> {
-        return Translation { pos: *self-camera_pos, inner: self }
+        Translation { pos: *self-camera_pos, inner: self }
     }
 }
 impl Translatable for Particle {
     fn translate(&self, camera_pos: Vec2) -> Translation<'_, Self> {
-        return Translation {
+        Translation {
             pos: self.pos-camera_pos,
             inner: self,
         }
@@ -1,5 +1,3 @@
-use std::collections::VecDeque;
-
 pub trait IndexOf<T> {
     fn index_of(&self, elem: &T) -> Option<usize>;
 }
@@ -7,7 +5,7 @@ impl <T> IndexOf<T> for Vec<T> where
     for<'a> &'a T: PartialEq<&'a T>
  { 
     fn index_of(&self, elem: &T) -> Option<usize> {
-        return self.iter().position(|e| e==&elem);
+        self.iter().position(|e| e == elem)
         
     }
 }
\ No newline at end of file